@@ -9,13 +9,15 @@ pub struct App{
 }
 
 pub enum AppState{
-    Disconnected,
+    /// Showing the connect form, with the reason the last connection ended.
+    Disconnected(Option<String>),
     Connected(WebSocketMeta),
 
 }
 
 pub enum Msg{
-    Connect(WebSocketMeta)
+    Connect(WebSocketMeta),
+    AuthFailed(String)
 }
 
 impl Component for App {
@@ -23,7 +25,7 @@ impl Component for App {
     type Properties = ();
 
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
-        App {component_link:link,state:AppState::Disconnected}
+        App {component_link:link,state:AppState::Disconnected(None)}
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
@@ -31,6 +33,9 @@ impl Component for App {
             Msg::Connect(meta)=>{
                 self.state=AppState::Connected(meta)
             }
+            Msg::AuthFailed(reason)=>{
+                self.state=AppState::Disconnected(Some(format!("Authentication failed: {}",reason)))
+            }
         }
         true
     }
@@ -41,18 +46,19 @@ impl Component for App {
                 <div>
                 {
                     match &self.state{
-                        AppState::Disconnected => {
+                        AppState::Disconnected(error) => {
                             html! {
                                 <ConnectForm onconnect=self.component_link.callback(|meta|{
                                     log::info!("Connect");
                                     Msg::Connect(meta)
                                     })
+                                    error=error.clone()
                                 />
                             }
                         }
                         AppState::Connected(meta)=>{
                             html!{
-                                <AppHome meta=meta/>
+                                <AppHome meta=meta onauthfailed=self.component_link.callback(Msg::AuthFailed)/>
                             }
                         }
                     }
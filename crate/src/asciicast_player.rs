@@ -0,0 +1,305 @@
+use serde::Deserialize;
+use yew::prelude::*;
+use yew::services::interval::{IntervalTask,IntervalService};
+
+use super::recording::RecordedEvent;
+use super::socket_agent::{AgentInput,AgentOutput,Request,RequestError,Response,SocketAgent};
+use super::terminal_src::{ScreenRenderer,VtParser};
+
+const TICK_MS:u64 = 50;
+const SPEEDS:[f64;4] = [0.5,1.0,2.0,4.0];
+
+/// Output and resize events of an asciicast v2 file.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Asciicast{
+    pub width:u16,
+    pub height:u16,
+    /// Seconds since the start and what happened then.
+    pub events:Vec<(f64,RecordedEvent)>
+}
+
+#[derive(Deserialize)]
+struct CastHeader{
+    version:u32,
+    width:u16,
+    height:u16
+}
+
+impl Asciicast{
+    /// Parses the header line and the "o" and "r" events; input events are
+    /// skipped.
+    pub fn parse(content:&str)->Result<Asciicast,String>{
+        let mut lines = content.lines().filter(|line|!line.trim().is_empty());
+        let header:CastHeader = match lines.next(){
+            Some(line)=>serde_json::from_str(line).map_err(|err|format!("Invalid header {}",err))?,
+            None=>return Err("Empty file".to_string())
+        };
+        if header.version!=2{
+            return Err(format!("Unsupported asciicast version {}",header.version));
+        }
+        let mut events = vec![];
+        for (idx,line) in lines.enumerate(){
+            let (time,kind,data):(f64,String,String) = serde_json::from_str(line)
+                .map_err(|err|format!("Invalid event on line {} {}",idx+2,err))?;
+            match kind.as_str(){
+                "o"=>events.push((time,RecordedEvent::Output(data))),
+                "r"=>{
+                    let mut parts = data.splitn(2,'x');
+                    let size = match (parts.next(),parts.next()){
+                        (Some(cols),Some(rows))=>rows.parse().ok().zip(cols.parse().ok()),
+                        _=>None
+                    };
+                    match size{
+                        Some((rows,cols))=>events.push((time,RecordedEvent::Resize(rows,cols))),
+                        None=>return Err(format!("Invalid size on line {} {}",idx+2,data))
+                    }
+                }
+                _=>{}
+            }
+        }
+        Ok(Asciicast{
+            width:header.width,
+            height:header.height,
+            events
+        })
+    }
+
+    pub fn duration(&self)->f64{
+        self.events.last().map(|(time,_)|*time).unwrap_or(0.0)
+    }
+}
+
+/// Read-only replay of an asciicast file from the workspace.
+pub struct AsciicastPlayer{
+    link:ComponentLink<Self>,
+    props:Props,
+    screenref:NodeRef,
+    agent_bridge:Box<dyn yew::Bridge<SocketAgent>>,
+    cast:Asciicast,
+    parser:VtParser,
+    screen:Option<ScreenRenderer>,
+    /// Index of the next event to play.
+    next_event:usize,
+    time:f64,
+    speed:f64,
+    ticker:Option<IntervalTask>,
+    error:Option<String>
+}
+
+#[derive(Clone,Properties,PartialEq)]
+pub struct Props{
+    pub path:String
+}
+
+pub enum Msg{
+    Loaded(Result<Response,RequestError>),
+    Tick,
+    TogglePlay,
+    Seek(f64),
+    SetSpeed(f64),
+    Ignore
+}
+
+impl AsciicastPlayer{
+    /// Plays every event up to `time`, starting over when seeking backwards.
+    fn advance_to(&mut self,time:f64){
+        if time<self.time{
+            self.parser=VtParser::new(self.cast.height,self.cast.width,0,0.0);
+            self.next_event=0;
+            if let Some(screen)=&mut self.screen{
+                screen.invalidate();
+            }
+        }
+        while let Some((event_time,event))=self.cast.events.get(self.next_event){
+            if *event_time>time{
+                break;
+            }
+            match event{
+                RecordedEvent::Output(output)=>self.parser.parse(output),
+                RecordedEvent::Resize(rows,cols)=>self.parser.set_size(*rows,*cols)
+            }
+            self.next_event+=1;
+        }
+        self.time=time;
+        if let Some(screen)=&mut self.screen{
+            screen.render(&self.parser,&[]);
+        }
+    }
+
+    fn is_playing(&self)->bool{
+        self.ticker.is_some()
+    }
+}
+
+impl Component for AsciicastPlayer{
+
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props:Props, link:ComponentLink<Self>) -> Self {
+        let mut agent_bridge = SocketAgent::bridge(link.callback(|data|match data{
+            AgentOutput::Reply(Request::FsOpen(_),response)=>Msg::Loaded(response),
+            _=>Msg::Ignore
+        }));
+        agent_bridge.send(AgentInput::Call(Request::FsOpen(props.path.clone())));
+        AsciicastPlayer{
+            link,
+            props,
+            screenref:NodeRef::default(),
+            agent_bridge,
+            cast:Asciicast::default(),
+            parser:VtParser::new(24,80,0,0.0),
+            screen:None,
+            next_event:0,
+            time:0.0,
+            speed:1.0,
+            ticker:None,
+            error:None
+        }
+    }
+
+    fn mounted(&mut self) -> ShouldRender {
+        if let Some(el)=self.screenref.cast::<web_sys::Element>(){
+            self.screen=Some(ScreenRenderer::new(el));
+        }
+        false
+    }
+
+    fn change(&mut self, props:Props) -> ShouldRender {
+        if self.props!=props{
+            self.props=props;
+            self.agent_bridge.send(AgentInput::Call(Request::FsOpen(self.props.path.clone())));
+            true
+        }else{
+            false
+        }
+    }
+
+    fn update(&mut self, msg: Msg) -> ShouldRender {
+        match msg{
+            Msg::Loaded(Ok(Response::FsOpen{content,..}))=>{
+                match Asciicast::parse(&content){
+                    Ok(cast)=>{
+                        self.ticker=None;
+                        self.parser=VtParser::new(cast.height,cast.width,0,0.0);
+                        self.cast=cast;
+                        self.next_event=0;
+                        self.time=0.0;
+                        self.error=None;
+                        if let Some(screen)=&mut self.screen{
+                            screen.invalidate();
+                        }
+                        self.advance_to(0.0);
+                    }
+                    Err(err)=>self.error=Some(err)
+                }
+                true
+            }
+            Msg::Loaded(Ok(_))=>false,
+            Msg::Loaded(Err(err))=>{
+                self.error=Some(format!("Cannot open {} ({:?})",self.props.path,err));
+                true
+            }
+            Msg::Tick=>{
+                let time = self.time+TICK_MS as f64/1000.0*self.speed;
+                self.advance_to(time.min(self.cast.duration()));
+                if self.next_event>=self.cast.events.len(){
+                    self.ticker=None;
+                }
+                true
+            }
+            Msg::TogglePlay=>{
+                if self.is_playing(){
+                    self.ticker=None;
+                }else{
+                    if self.next_event>=self.cast.events.len(){
+                        self.advance_to(0.0);
+                    }
+                    self.ticker=Some(IntervalService::new().spawn(
+                        std::time::Duration::from_millis(TICK_MS),
+                        self.link.callback(|_|Msg::Tick)
+                    ));
+                }
+                true
+            }
+            Msg::Seek(time)=>{
+                self.advance_to(time.max(0.0).min(self.cast.duration()));
+                true
+            }
+            Msg::SetSpeed(speed)=>{
+                self.speed=speed;
+                true
+            }
+            Msg::Ignore=>false
+        }
+    }
+
+    fn view(&self) -> Html {
+        html!{
+            <div class="box">
+                <div class="field has-addons">
+                    <div class="control">
+                        <button class="button is-small" onclick=self.link.callback(|_|Msg::TogglePlay)>
+                            <span class="icon is-small">
+                                <i class={
+                                    if self.is_playing(){
+                                        "fas fa-pause"
+                                    }else{
+                                        "fas fa-play"
+                                    }
+                                }></i>
+                            </span>
+                        </button>
+                    </div>
+                    <div class="control is-expanded">
+                        <input type="range" style="width:100%;" min="0" max=self.cast.duration().to_string() step="0.1" value=self.time.to_string()
+                            oninput=self.link.callback(|e:InputData|match e.value.parse(){
+                                Ok(time)=>Msg::Seek(time),
+                                Err(_)=>Msg::Ignore
+                            })/>
+                    </div>
+                    <p class="help" style="margin:0 0.5em;">{format!("{:.1}s / {:.1}s",self.time,self.cast.duration())}</p>
+                    <div class="control">
+                        <div class="select is-small">
+                            <select onchange=self.link.callback(|e:ChangeData|match e{
+                                ChangeData::Select(el)=>el.value().parse().map(Msg::SetSpeed).unwrap_or(Msg::Ignore),
+                                _=>Msg::Ignore
+                            })>
+                                { for SPEEDS.iter().map(|speed| html!{
+                                    <option value=speed.to_string() selected=*speed==self.speed>{format!("{}x",speed)}</option>
+                                })}
+                            </select>
+                        </div>
+                    </div>
+                </div>
+                {
+                    match &self.error{
+                        Some(err)=>html!{ <p class="help is-danger">{err}</p> },
+                        None=>html!{}
+                    }
+                }
+                <div class="dcode-term" style="font-family:Courier New;white-space:pre;line-height:1.2;overflow:auto;" ref=self.screenref.clone()></div>
+            </div>
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn parse_keeps_output_and_resizes(){
+        let cast = Asciicast::parse(concat!(
+            "{\"version\":2,\"width\":80,\"height\":24}\n",
+            "[0.5,\"o\",\"hi\"]\n",
+            "[0.7,\"i\",\"x\"]\n",
+            "[1.0,\"r\",\"100x30\"]\n"
+        )).unwrap();
+        assert_eq!(cast.events,vec![
+            (0.5,RecordedEvent::Output("hi".to_string())),
+            (1.0,RecordedEvent::Resize(30,100))
+        ]);
+        assert!(Asciicast::parse("{\"version\":2,\"width\":80,\"height\":24}\n[1.0,\"r\",\"wide\"]").is_err());
+    }
+}
@@ -6,32 +6,205 @@ use std::convert::TryInto;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{ MessageEvent, WebSocket, HtmlInputElement};
-use super::socket_agent::{SocketAgent,AgentInput,AgentOutput};
+use serde::{Serialize,Deserialize};
+use super::socket_agent::{SocketAgent,AgentInput,AgentOutput,OverflowPolicy};
+use super::profiles::{ConnectionProfile,ProfileStore};
 
-#[derive(Clone,PartialEq)]
+#[derive(Clone,PartialEq,Debug,Serialize,Deserialize)]
 pub struct WebSocketMeta{
     // pub ws:WebSocket,
-    pub url:String
+    /// Normalized `ws://` or `wss://` URL the socket is opened with.
+    pub url:String,
+    pub secure:bool,
+    pub host:String,
+    pub port:Option<u16>,
+    pub path:String,
+    pub query:Option<String>
+}
+
+const DEFAULT_PATH:&str = "/ws";
+
+impl WebSocketMeta{
+    /// Parses what the user typed in the form. A bare `host[:port]` keeps
+    /// working and gets the `/ws` path; the scheme defaults to `wss` when the
+    /// page itself is served over https.
+    pub fn parse(input:&str)->Result<WebSocketMeta,String>{
+        WebSocketMeta::parse_with_default(input,WebSocketMeta::default_scheme())
+    }
+
+    fn parse_with_default(input:&str,default_scheme:&str)->Result<WebSocketMeta,String>{
+        let input = input.trim();
+        if input.is_empty(){
+            return Err("Enter a backend URL".to_string());
+        }
+        let (scheme,rest) = match split_scheme(input){
+            Some((scheme,rest))=>(scheme.to_lowercase(),rest),
+            None=>(default_scheme.to_string(),input)
+        };
+        let secure = match scheme.as_str(){
+            "ws" | "http"=>false,
+            "wss" | "https"=>true,
+            other=>return Err(format!("Unsupported scheme {}://, use ws:// or wss://",other))
+        };
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (rest,query) = match rest.find('?'){
+            Some(idx)=>(&rest[..idx],Some(&rest[idx+1..])),
+            None=>(rest,None)
+        };
+        let (authority,path) = match rest.find('/'){
+            Some(idx)=>(&rest[..idx],rest[idx..].to_string()),
+            None=>(rest,DEFAULT_PATH.to_string())
+        };
+        // Credentials are not sent with a WebSocket handshake.
+        let authority = authority.rsplit('@').next().unwrap_or_default();
+        let (host,port) = if authority.starts_with('['){
+            // IPv6 literal, `[::1]:8080`
+            match authority.find(']'){
+                Some(end)=>(&authority[..=end],&authority[end+1..]),
+                None=>return Err(format!("{} is not a valid URL",input))
+            }
+        }else{
+            match authority.find(':'){
+                Some(idx)=>(&authority[..idx],&authority[idx..]),
+                None=>(authority,"")
+            }
+        };
+        if host.is_empty(){
+            return Err("URL is missing a host".to_string());
+        }
+        let valid_host = host.starts_with('[')
+            || host.chars().all(|c|c.is_ascii_alphanumeric() || c=='-' || c=='.' || c=='_');
+        if !valid_host{
+            return Err(format!("{} is not a valid URL",input));
+        }
+        let port = match port.strip_prefix(':'){
+            None|Some("")=>None,
+            Some(port)=>Some(port.parse::<u16>().map_err(|_|format!("Invalid port {}",port))?)
+        };
+        // Like the browser, leave out the scheme's default port.
+        let port = port.filter(|port|*port!=if secure { 443 } else { 80 });
+        let query = query.filter(|query|!query.is_empty()).map(str::to_string);
+
+        let mut meta = WebSocketMeta{
+            url:String::new(),
+            secure,
+            host:host.to_lowercase(),
+            port,
+            path,
+            query
+        };
+        meta.url=meta.to_url();
+        Ok(meta)
+    }
+
+    fn default_scheme()->&'static str{
+        let protocol = yew::utils::window().location().protocol().unwrap_or_default();
+        if protocol=="https:"{
+            "wss"
+        }else{
+            "ws"
+        }
+    }
+
+    pub fn to_url(&self)->String{
+        let mut url = format!("{}://{}",if self.secure {"wss"} else {"ws"},self.host);
+        if let Some(port)=self.port{
+            url+=&format!(":{}",port);
+        }
+        url+=&self.path;
+        if let Some(query)=&self.query{
+            url+=&format!("?{}",query);
+        }
+        url
+    }
+}
+
+/// Splits `scheme://rest`. The part before `://` only counts as a scheme
+/// when it is a scheme token, so a `://` inside the path or query (as in
+/// `host/ws?next=http://x`) is left alone.
+fn split_scheme(input:&str)->Option<(&str,&str)>{
+    let idx = input.find("://")?;
+    let scheme = &input[..idx];
+    let mut chars = scheme.chars();
+    let valid = chars.next().map(|c|c.is_ascii_alphabetic()).unwrap_or(false)
+        && chars.all(|c|c.is_ascii_alphanumeric() || c=='+' || c=='-' || c=='.');
+    if valid{
+        Some((scheme,&input[idx+3..]))
+    }else{
+        None
+    }
 }
 
 pub struct ConnectForm {
     link: ComponentLink<Self>,
     onconnect: Callback<WebSocketMeta>,
     url_ref: NodeRef,
+    token_ref: NodeRef,
+    profile_name_ref: NodeRef,
+    project_ref: NodeRef,
     is_connecting:bool,
+    error:Option<String>,
+    store:ProfileStore,
+    selected_profile:Option<String>,
+    /// Whether saving a profile also stores its token in localStorage.
+    remember_token:bool,
+    overflow:OverflowPolicy,
     socket_agent:Box<yew::Bridge<SocketAgent>>
 }
 
 pub enum Msg {
     Clicked,
     Connected(WebSocketMeta),
-    Error,
+    Error(String),
+    SelectSaved(String),
+    SaveProfile,
+    DeleteProfile,
+    ToggleAutoConnect,
+    ToggleRememberToken,
+    SetOverflow(OverflowPolicy),
     Ignore
 }
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct Props {
     pub onconnect: Callback<WebSocketMeta>,
+    /// Why the previous connection ended, shown until the next attempt.
+    #[prop_or_default]
+    pub error: Option<String>,
+}
+
+impl ConnectForm {
+    fn input(node:&NodeRef)->HtmlInputElement{
+        node.cast().expect("Not input Element")
+    }
+
+    fn connect(&mut self){
+        let el=ConnectForm::input(&self.url_ref);
+        // let ws = WebSocket::new(&format!("ws://{}/ws",el.value()));
+        // self.url_ref.cast::<Self>();
+        let token = Some(ConnectForm::input(&self.token_ref).value()).filter(|token|!token.is_empty());
+        match WebSocketMeta::parse(&el.value()){
+            Ok(meta)=>{
+                self.socket_agent.send(AgentInput::ConnectSocket(meta,token,self.overflow));
+                self.is_connecting=true;
+                self.error=None;
+            }
+            Err(err)=>{
+                self.error=Some(err);
+            }
+        }
+    }
+
+    fn fill_profile(&mut self,name:&str){
+        if let Some(profile)=self.store.profile(name).cloned(){
+            ConnectForm::input(&self.url_ref).set_value(&profile.url);
+            ConnectForm::input(&self.token_ref).set_value(&ProfileStore::token(&profile).unwrap_or_default());
+            ConnectForm::input(&self.profile_name_ref).set_value(&profile.name);
+            ConnectForm::input(&self.project_ref).set_value(&profile.default_project.unwrap_or_default());
+            self.remember_token=profile.token_ref.is_some();
+            self.selected_profile=Some(profile.name);
+        }
+    }
 }
 
 impl Component for ConnectForm {
@@ -41,8 +214,9 @@ impl Component for ConnectForm {
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
         let agent = SocketAgent::bridge(link.callback(|data|{
             match data{
-                AgentOutput::Connected(url)=>Msg::Connected(WebSocketMeta{url}),
-                AgentOutput::Disconnected | AgentOutput::ErrorConnecting => Msg::Error,
+                AgentOutput::Connected(meta)=>Msg::Connected(meta),
+                AgentOutput::AuthFailed(reason)=>Msg::Error(format!("Authentication failed: {}",reason)),
+                AgentOutput::Disconnected | AgentOutput::ErrorConnecting => Msg::Error("Could not connect to backend".to_string()),
                 _=>Msg::Ignore
             }
         }));
@@ -50,27 +224,110 @@ impl Component for ConnectForm {
             link,
             onconnect: props.onconnect,
             url_ref: NodeRef::default(),
+            token_ref: NodeRef::default(),
+            profile_name_ref: NodeRef::default(),
+            project_ref: NodeRef::default(),
             is_connecting:false,
+            error:props.error,
+            store:ProfileStore::load(),
+            selected_profile:None,
+            remember_token:false,
+            overflow:OverflowPolicy::default(),
             socket_agent:agent
         }
     }
 
+    fn mounted(&mut self) -> ShouldRender {
+        let name = match self.store.last_profile.clone(){
+            Some(name)=>name,
+            None=>return false
+        };
+        // Back here after a rejected token: prefill, but let the user fix it
+        // instead of auto-connecting into the same failure.
+        if self.error.is_some(){
+            self.fill_profile(&name);
+            return true;
+        }
+        if !self.store.auto_connect{
+            return false;
+        }
+        self.fill_profile(&name);
+        if self.selected_profile.is_some(){
+            self.connect();
+            return true;
+        }
+        false
+    }
+
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
             Msg::Clicked => {
                 // self.onconnect.emit(());
-
-                let el:HtmlInputElement=self.url_ref.cast().expect("Not input Element");
-                // let ws = WebSocket::new(&format!("ws://{}/ws",el.value()));
-                // self.url_ref.cast::<Self>();
-                self.socket_agent.send(AgentInput::ConnectSocket(el.value()));
-                self.is_connecting=true;
+                self.connect();
             }
             Msg::Connected(meta)=>{
+                let from_profile = self.selected_profile.as_ref()
+                    .and_then(|name|self.store.profile(name))
+                    .filter(|profile|profile.url==meta.url)
+                    .map(|profile|profile.name.clone());
+                self.store.last_profile=from_profile;
+                self.store.push_recent(&meta.url);
+                self.store.save();
                 self.onconnect.emit(meta);
             }
-            Msg::Error=>{
+            Msg::Error(err)=>{
                 self.is_connecting=false;
+                self.error=Some(err);
+            }
+            Msg::SelectSaved(value)=>{
+                if let Some(name)=value.strip_prefix("profile:"){
+                    self.fill_profile(name);
+                }else if let Some(url)=value.strip_prefix("recent:"){
+                    ConnectForm::input(&self.url_ref).set_value(url);
+                    self.selected_profile=None;
+                }
+            }
+            Msg::SaveProfile=>{
+                let name = ConnectForm::input(&self.profile_name_ref).value().trim().to_string();
+                if name.is_empty(){
+                    self.error=Some("Enter a profile name".to_string());
+                    return true;
+                }
+                match WebSocketMeta::parse(&ConnectForm::input(&self.url_ref).value()){
+                    Ok(meta)=>{
+                        let token = Some(ConnectForm::input(&self.token_ref).value())
+                            .filter(|token|self.remember_token && !token.is_empty());
+                        let project = ConnectForm::input(&self.project_ref).value().trim().to_string();
+                        self.store.upsert(ConnectionProfile{
+                            name:name.clone(),
+                            url:meta.url,
+                            token_ref:None,
+                            default_project:Some(project).filter(|project|!project.is_empty())
+                        },token);
+                        self.store.save();
+                        self.selected_profile=Some(name);
+                        self.error=None;
+                    }
+                    Err(err)=>{
+                        self.error=Some(err);
+                    }
+                }
+            }
+            Msg::DeleteProfile=>{
+                if let Some(name)=self.selected_profile.take(){
+                    self.store.remove(&name);
+                    self.store.save();
+                }
+            }
+            Msg::ToggleAutoConnect=>{
+                self.store.auto_connect = !self.store.auto_connect;
+                self.store.save();
+            }
+            Msg::ToggleRememberToken=>{
+                self.remember_token = !self.remember_token;
+            }
+            Msg::SetOverflow(overflow)=>{
+                self.overflow=overflow;
             }
             Msg::Ignore=>{
                 return false;
@@ -91,10 +348,13 @@ impl Component for ConnectForm {
         }
         html! {
 
-
+            <div>
             <div class="field has-addons">
                 <div class="control is-expanded">
-                    <input ref=self.url_ref.clone() class="input" type="text" placeholder="Backend URL"></input>
+                    <input ref=self.url_ref.clone() class="input" type="text" placeholder="Backend URL (host:port or wss://host/path)"></input>
+                </div>
+                <div class="control">
+                    <input ref=self.token_ref.clone() class="input" type="password" placeholder="Access token"></input>
                 </div>
                 <div class="control">
                     <a class=classs onclick=self.link.callback(|_|Msg::Clicked)>
@@ -102,7 +362,124 @@ impl Component for ConnectForm {
                     </a>
                 </div>
             </div>
+            {
+                match &self.error{
+                    Some(err)=>html!{
+                        <p class="help is-danger">{err}</p>
+                    },
+                    None=>html!{}
+                }
+            }
+            <div class="field is-grouped is-grouped-multiline">
+                <div class="control">
+                    <div class="select is-small">
+                        <select onchange=self.link.callback(|e:ChangeData|match e{
+                            ChangeData::Select(el)=>Msg::SelectSaved(el.value()),
+                            _=>Msg::Ignore
+                        })>
+                            <option value="" selected=self.selected_profile.is_none()>{"Saved servers"}</option>
+                            <optgroup label="Profiles">
+                            { for self.store.profiles.iter().map(|profile| html!{
+                                <option value=format!("profile:{}",profile.name) selected=self.selected_profile.as_ref()==Some(&profile.name)>{&profile.name}</option>
+                            })}
+                            </optgroup>
+                            <optgroup label="Recent">
+                            { for self.store.recent.iter().map(|url| html!{
+                                <option value=format!("recent:{}",url)>{url}</option>
+                            })}
+                            </optgroup>
+                        </select>
+                    </div>
+                </div>
+                <div class="control">
+                    <input ref=self.profile_name_ref.clone() class="input is-small" type="text" placeholder="Profile name"></input>
+                </div>
+                <div class="control">
+                    <input ref=self.project_ref.clone() class="input is-small" type="text" placeholder="Default project"></input>
+                </div>
+                <div class="control">
+                    <label class="checkbox" title="Stored unencrypted in this browser's local storage">
+                        <input type="checkbox" checked=self.remember_token onclick=self.link.callback(|_|Msg::ToggleRememberToken)></input>
+                        {" Remember token"}
+                    </label>
+                </div>
+                <div class="control">
+                    <a class="button is-small" onclick=self.link.callback(|_|Msg::SaveProfile)>{"Save profile"}</a>
+                </div>
+                {
+                    if self.selected_profile.is_some(){
+                        html!{
+                            <div class="control">
+                                <a class="button is-small is-danger is-outlined" onclick=self.link.callback(|_|Msg::DeleteProfile)>{"Delete"}</a>
+                            </div>
+                        }
+                    }else{
+                        html!{}
+                    }
+                }
+                <div class="control">
+                    <label class="checkbox">
+                        <input type="checkbox" checked=self.store.auto_connect onclick=self.link.callback(|_|Msg::ToggleAutoConnect)></input>
+                        {" Auto-connect to last profile"}
+                    </label>
+                </div>
+                <div class="control">
+                    <div class="select is-small" title="What to drop when too many messages are waiting for the connection">
+                        <select onchange=self.link.callback(|e:ChangeData|match e{
+                            ChangeData::Select(el)=>OverflowPolicy::from_name(&el.value()).map(Msg::SetOverflow).unwrap_or(Msg::Ignore),
+                            _=>Msg::Ignore
+                        })>
+                            <option value=OverflowPolicy::DropOldest.name() selected=self.overflow==OverflowPolicy::DropOldest>{"Queue full: drop oldest"}</option>
+                            <option value=OverflowPolicy::DropNewest.name() selected=self.overflow==OverflowPolicy::DropNewest>{"Queue full: drop newest"}</option>
+                        </select>
+                    </div>
+                </div>
+            </div>
+            </div>
 
         }
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn parse(input:&str)->Result<WebSocketMeta,String>{
+        WebSocketMeta::parse_with_default(input,"ws")
+    }
+
+    #[test]
+    fn bare_host_gets_default_scheme_and_path(){
+        let meta = parse(" localhost:8080 ").unwrap();
+        assert_eq!(meta.url,"ws://localhost:8080/ws");
+        assert_eq!((meta.secure,meta.port,meta.path.as_str()),(false,Some(8080),"/ws"));
+        assert_eq!(WebSocketMeta::parse_with_default("example.com","wss").unwrap().url,"wss://example.com/ws");
+    }
+
+    #[test]
+    fn full_urls_keep_path_and_query(){
+        let meta = parse("WSS://Example.com:9443/term/ws?token=a#x").unwrap();
+        assert_eq!(meta.url,"wss://example.com:9443/term/ws?token=a");
+        assert_eq!(meta.query,Some("token=a".to_string()));
+        assert_eq!(parse("https://example.com:443/").unwrap().url,"wss://example.com/");
+        assert_eq!(parse("http://[::1]:8080").unwrap().url,"ws://[::1]:8080/ws");
+    }
+
+    #[test]
+    fn scheme_separator_in_the_query_is_not_a_scheme(){
+        let meta = parse("host:8080/ws?next=http://x").unwrap();
+        assert_eq!(meta.url,"ws://host:8080/ws?next=http://x");
+        assert_eq!(split_scheme("host/a://b"),None);
+        assert_eq!(split_scheme("ws://host"),Some(("ws","host")));
+    }
+
+    #[test]
+    fn invalid_input_is_reported(){
+        assert_eq!(parse("  "),Err("Enter a backend URL".to_string()));
+        assert_eq!(parse("ftp://host"),Err("Unsupported scheme ftp://, use ws:// or wss://".to_string()));
+        assert_eq!(parse("host:99999"),Err("Invalid port 99999".to_string()));
+        assert_eq!(parse("ws://:8080/ws"),Err("URL is missing a host".to_string()));
+        assert!(parse("ho st").is_err());
+    }
+}
@@ -4,7 +4,7 @@ use web_sys::HtmlElement;
 
 use super::editor_src::AceEditor;
 use super::file_manager::{FsEntry};
-use super::socket_agent::{AgentInput,AgentOutput,SocketAgent,TransferData};
+use super::socket_agent::{AgentInput,AgentOutput,Request,RequestError,Response,SocketAgent};
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use maplit::hashmap;
@@ -38,7 +38,8 @@ pub struct Editor{
     clean_interval_check:IntervalTask
 }
 pub enum Msg{
-    SocketMessage(TransferData),
+    Reconnected,
+    Reply(Request,Result<Response,RequestError>),
     CheckClean,
     Save,
     Ignore
@@ -59,8 +60,10 @@ impl Component for Editor{
     fn create(props: Self::Properties, link: ComponentLink<Self>)->Self{
         let propsc = props.file.clone();
         let mut bridge = SocketAgent::bridge(link.callback(move|out|{
-            if let AgentOutput::SocketMessage(data)=out{
-                Msg::SocketMessage(data)
+            if let AgentOutput::Reply(request,response)=out{
+                Msg::Reply(request,response)
+            } else if let AgentOutput::Connected(_)=out{
+                Msg::Reconnected
             } else if let AgentOutput::SaveFile(file)=out{
                 if file==propsc.clone(){
                     Msg::Save
@@ -71,15 +74,7 @@ impl Component for Editor{
                 Msg::Ignore
             }
         }));
-        bridge.send(AgentInput::SendData(
-            TransferData{
-                command:"fs".to_string(),
-                value:"open".to_string(),
-                args:vec![
-                    props.file.path.to_string()
-                ]
-            }
-        ));
+        bridge.send(AgentInput::Call(Request::FsOpen(props.file.path.to_string())));
         let task = IntervalService::new().spawn(std::time::Duration::from_secs(1),link.callback(|_|Msg::CheckClean));
         Self{
             editor_ref:NodeRef::default(),
@@ -104,33 +99,31 @@ impl Component for Editor{
     fn update(&mut self,msg: Self::Message)->ShouldRender{
         match msg{
             Msg::Ignore=>false,
-            Msg::SocketMessage(msg)=>{
-                if msg.command=="fs"{
-                    if msg.value=="open"{
-                        if let Some(fname) = msg.args.get(0){
-                            if self.props.file.path==fname.as_str(){
-                                if let Some(editor)=&mut self.editor{
-                                    if let Some(val)=msg.args.get(1){
-                                        editor.set_value(&val);
-                                        editor.markClean();
-                                    }
-                                }
-                            }
+            Msg::Reconnected=>{
+                // Reload from disk unless there are unsaved edits to keep.
+                if let Some(editor)=&self.editor{
+                    if editor.isClean(){
+                        self.bridge.send(AgentInput::Call(Request::FsOpen(self.props.file.path.to_string())));
+                    }
+                }
+                false
+            }
+            Msg::Reply(request,response)=>{
+                match response{
+                    Ok(Response::FsOpen{content,..})=>{
+                        if let Some(editor)=&mut self.editor{
+                            editor.set_value(&content);
+                            editor.markClean();
                         }
                     }
-                    if msg.value=="save"{
-                        if let Some(fname)=msg.args.get(0){
-                            if let Ok(ff)=serde_json::from_str::<FsEntry>(&fname){
-                                if self.props.file==ff{
-                                    if let Some(editor)=&self.editor{
-                                        editor.markClean();
-                                        self.props.clean_callback.emit((self.props.file.clone(),editor.isClean()))
-                                    }
-
-                                }
-                            }
+                    Ok(Response::FsSave(_))=>{
+                        if let Some(editor)=&self.editor{
+                            editor.markClean();
+                            self.props.clean_callback.emit((self.props.file.clone(),editor.isClean()))
                         }
                     }
+                    Ok(_)=>{}
+                    Err(err)=>log::error!("{:?} failed {:?}",request,err)
                 }
                 false
             },
@@ -142,16 +135,10 @@ impl Component for Editor{
             }
             Msg::Save=>{
                 if let Some(editor)=&self.editor{
-                    if let Ok(ffs)=serde_json::to_string(&self.props.file){
-                        let tdata = TransferData{
-                            command:"fs".to_string(),
-                            value:"save".to_string(),
-                            args:vec![
-                                ffs,editor.get_value()
-                            ]
-                        };
-                        self.bridge.send(AgentInput::SendData(tdata));
-                    }
+                    self.bridge.send(AgentInput::Call(Request::FsSave{
+                        file:self.props.file.clone(),
+                        content:editor.get_value()
+                    }));
                 }
                 false
             }
@@ -1,7 +1,7 @@
 use yew::prelude::*;
 use super::new_project_dialog::ProjectConfig;
 
-use super::socket_agent::{AgentInput,AgentOutput,SocketAgent,TransferData};
+use super::socket_agent::{AgentInput,AgentOutput,Request,Response,SocketAgent};
 use serde::{Serialize,Deserialize};
 use yew::services::{IntervalService};
 use yew::services::interval::IntervalTask;
@@ -26,7 +26,7 @@ pub struct FsEntry {
 
 
 pub enum Msg {
-    SocketMessage(TransferData),
+    SocketMessage(Response),
     Refresh,
     OpenFolder(String),
     OpenFile(FsEntry),
@@ -50,7 +50,7 @@ impl Component for FileManager{
     fn create(props: Self::Properties, link: ComponentLink<Self>)->Self{
 
         let agent_bridge=SocketAgent::bridge(link.callback(|data|match data{
-            AgentOutput::SocketMessage(data)=>Msg::SocketMessage(data),
+            AgentOutput::Response(response)=>Msg::SocketMessage(response),
             _ => Msg::Ignore
         }));
         let task = IntervalService::new().spawn(std::time::Duration::from_secs(1), link.callback(|_|Msg::Refresh));
@@ -82,9 +82,8 @@ impl Component for FileManager{
 
         match msg{
             Msg::Ignore=>false,
-            Msg::SocketMessage(data)=>{
-                if data.command=="fs" && data.value=="list"{
-                    let entries:Vec<FsEntry> = data.args.iter().filter_map(|entry|serde_json::from_str(entry).ok()).collect();
+            Msg::SocketMessage(response)=>{
+                if let Response::FsList(entries)=response{
                     self.entries=entries;
                     true
                 }else{
@@ -93,13 +92,7 @@ impl Component for FileManager{
             }
             Msg::OpenFolder(path)=>{
                 self.current_dir=path.clone();
-                self.agent_bridge.send(AgentInput::SendData(
-                    TransferData{
-                        command:"fs".to_string(),
-                        value:"list".to_string(),
-                        args:vec![path]
-                    }
-                ));
+                self.agent_bridge.send(AgentInput::Send(Request::FsList(path)));
                 false
             }
             Msg::OpenFile(file)=>{
@@ -107,13 +100,7 @@ impl Component for FileManager{
                 false
             }
             Msg::Refresh=>{
-                self.agent_bridge.send(AgentInput::SendData(
-                    TransferData{
-                        command:"fs".to_string(),
-                        value:"list".to_string(),
-                        args:vec![self.current_dir.to_string()]
-                    }
-                ));
+                self.agent_bridge.send(AgentInput::Send(Request::FsList(self.current_dir.to_string())));
                 false
             }
             Msg::OpenNewDialog=>{
@@ -130,25 +117,14 @@ impl Component for FileManager{
                 }else{
                     file.path=format!("{}/{}",self.current_dir,file.name);
                 }
-                let tdata = {
+                let request = {
                     if file.is_dir{
-                        TransferData{
-                            command:"fs".to_string(),
-                            value:"new_dir".to_string(),
-                            args:vec![file.path]
-                        }
+                        Request::FsNewDir(file.path)
                     }else{
-                        let ffs = serde_json::to_string(&file).expect("Cant convert to string");
-                        TransferData{
-                            command:"fs".to_string(),
-                            value:"new".to_string(),
-                            args:vec![
-                                ffs
-                            ]
-                        }
+                        Request::FsNew(file)
                     }
                 };
-                self.agent_bridge.send(AgentInput::SendData(tdata));
+                self.agent_bridge.send(AgentInput::Send(request));
                 self.open_new_dialog=false;
                 false
             }
@@ -156,13 +132,7 @@ impl Component for FileManager{
     }
 
     fn mounted(&mut self)->ShouldRender{
-        self.agent_bridge.send(AgentInput::SendData(
-            TransferData{
-                command:"fs".to_string(),
-                value:"list".to_string(),
-                args:vec![self.current_dir.clone()]
-            }
-        ));
+        self.agent_bridge.send(AgentInput::Send(Request::FsList(self.current_dir.clone())));
         false
     }
 
@@ -1,31 +1,46 @@
 use serde::{Deserialize, Serialize};
 use yew::prelude::*;
 
-use super::new_project_dialog::{NewProjectDialog, ProjectConfig};
+use super::new_project_dialog::{NewProjectDialog, ProjectConfig, Template};
+use super::project_picker::{ProjectPicker, PROJECTS_DIR};
+use super::profiles::ProfileStore;
 use maplit::hashmap;
-use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{HtmlInputElement, MessageEvent, WebSocket};
 
 use super::connect_form::WebSocketMeta;
-use super::socket_agent::TransferData;
 use super::terminal::TerminalComp;
 use super::file_manager::{FileManager,FsEntry};
 use super::editor::Editor;
-use super::socket_agent::{AgentInput, AgentOutput, SocketAgent};
+use super::asciicast_player::AsciicastPlayer;
+use super::socket_agent::{AgentInput, AgentOutput, Request, Response, SocketAgent};
+use super::pane_layout::{FocusDirection, PaneNode, PaneRect, SplitDirection};
+use gloo::events::{EventListener, EventListenerOptions, EventListenerPhase};
 
 pub struct AppHome {
     meta: WebSocketMeta,
+    onauthfailed: Callback<String>,
+    /// Loaded once on creation; changes are saved back through this copy.
+    profiles: ProfileStore,
     project_config: Option<ProjectConfig>,
+    show_new_project: bool,
     link: ComponentLink<Self>,
     state: ConnectedStateData,
     socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
+    /// Ctrl+Shift(+Alt)+Arrow pane shortcuts, caught before the terminals see them.
+    pane_keys: Option<EventListener>,
+    /// Created on the first click, browsers keep contexts made without a
+    /// user gesture suspended.
+    audio: Option<web_sys::AudioContext>,
 }
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct Props {
     pub meta: WebSocketMeta,
+    /// The backend rejected the token while reconnecting; the agent has
+    /// given up and the connection has to be set up again.
+    pub onauthfailed: Callback<String>,
 }
 
 #[derive(Debug,Default,Clone,PartialEq)]
@@ -36,13 +51,33 @@ pub struct Panels{
 
 #[derive(Clone,PartialEq)]
 pub struct TerminalHost {
+    /// Backend process id, never reused within a page session.
+    pub id: String,
     pub title: String,
     pub is_active: bool,
     pub init_dir: String,
     pub init_cmd: String,
+    /// Bumped when the backend process had to be respawned after a reconnect.
+    pub generation: u32,
+    /// The backend process was already running when the page loaded; its
+    /// screen is replayed instead of running the init command.
+    pub reattach: bool,
+    /// Rang or notified while not focused; cleared when focused.
+    pub bell: bool,
     // terminal: Html,
 }
 
+/// A terminal tab, holding one or more panes.
+#[derive(Clone,PartialEq)]
+pub struct TerminalTab {
+    /// Id of the terminal the tab was opened with, kept as its key.
+    pub id: String,
+    pub layout: PaneNode,
+    /// Pane receiving keyboard input.
+    pub focused: String,
+    pub is_active: bool,
+}
+
 #[derive(Clone,PartialEq)]
 pub struct EditorHost {
     pub file: FsEntry,
@@ -50,23 +85,60 @@ pub struct EditorHost {
     pub is_clean:bool
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionStatus {
+    Connected,
+    Reconnecting(u32),
+    Disconnected,
+}
+
+impl Default for ConnectionStatus {
+    fn default() -> Self {
+        ConnectionStatus::Connected
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct ConnectedStateData {
     ping: f64,
-    terminals: HashMap<String, TerminalHost>,
+    status: ConnectionStatus,
+    resuming: bool,
+    /// Waiting for the first process list to pick up terminals that outlived a reload.
+    discovering: bool,
+    /// Every terminal process; `is_active` marks the focused pane of the active tab.
+    terminals: Vec<TerminalHost>,
+    /// In tab order.
+    tabs: Vec<TerminalTab>,
+    next_term: u32,
+    /// Tab being dragged to a new position.
+    dragged_tab: Option<String>,
     openfiles: Vec<EditorHost>
 }
 
 pub enum Msg {
     PingUpdate(f64),
+    UnlockAudio,
+    StatusChange(ConnectionStatus),
+    AuthFailed(String),
     CreateProject(ProjectConfig),
-    SocketMessage(TransferData),
+    OpenProject(String),
+    ShowNewProject(bool),
+    SocketMessage(Response),
     SwitchTerminal(String),
     RenameTerminal(String,String),
     Update,
     Ignore,
     CreateTerm(String),
     RemoveTerm(String),
+    Bell(String),
+    CloseTab(String),
+    SplitTerm(SplitDirection),
+    FocusPane(String),
+    MoveFocus(FocusDirection),
+    ResizePane(FocusDirection),
+    DragTerm(String),
+    DropTerm(String),
+    SetScrollback(usize),
     SetPanels(Panels),
     OpenFile(FsEntry),
     CleanStatus((FsEntry,bool)),
@@ -80,23 +152,154 @@ impl AppHome {
         let date = js_sys::Date::new_0();
         let msec = date.get_time();
 
-        let pingdata = TransferData {
-            command: "ping".to_string(),
-            value: "0".to_string(),
-            args: vec![format!("{}", msec)],
-        };
+        self.send_request(Request::Ping(msec));
+    }
+
+    fn remember_project(&mut self, name: &str) {
+        self.profiles.set_last_project(&self.meta.url, name);
+        self.profiles.save();
+    }
+
+    fn project_dir(&self) -> String {
+        format!(
+            "{}/{}",
+            PROJECTS_DIR,
+            self.project_config.clone().unwrap_or_default().name
+        )
+    }
+
+    fn terminal_mut(&mut self, termid: &str) -> Option<&mut TerminalHost> {
+        self.state.terminals.iter_mut().find(|term| term.id == termid)
+    }
+
+    /// Next `term<n>` above every id handed out so far, including reattached
+    /// ones, so a closed terminal's id is never given to a new one.
+    fn allocate_term_id(&mut self) -> String {
+        let highest = self
+            .state
+            .terminals
+            .iter()
+            .filter_map(|term| term.id.trim_start_matches("term").parse::<u32>().ok())
+            .max()
+            .unwrap_or(0);
+        let next = self.state.next_term.max(highest + 1);
+        self.state.next_term = next + 1;
+        format!("term{}", next)
+    }
+
+    fn new_terminal(&mut self, termid: &str, init_cmd: String, reattach: bool) {
+        self.state.terminals.push(TerminalHost {
+            id: termid.to_string(),
+            is_active: false,
+            title: termid.to_string(),
+            init_dir: self.project_dir(),
+            init_cmd,
+            generation: 0,
+            reattach,
+            bell: false,
+        });
+    }
+
+    fn open_tab(&mut self, termid: &str, activate: bool) {
+        if activate {
+            for tab in &mut self.state.tabs {
+                tab.is_active = false;
+            }
+        }
+        self.state.tabs.push(TerminalTab {
+            id: termid.to_string(),
+            layout: PaneNode::Leaf(termid.to_string()),
+            focused: termid.to_string(),
+            is_active: activate,
+        });
+    }
+
+    fn active_tab_mut(&mut self) -> Option<&mut TerminalTab> {
+        self.state.tabs.iter_mut().find(|tab| tab.is_active)
+    }
+
+    fn activate_tab(&mut self, tabid: &str) {
+        for tab in &mut self.state.tabs {
+            tab.is_active = tab.id == tabid;
+        }
+        self.sync_focus();
+    }
+
+    /// Makes sure a tab is active and marks its focused pane as the active terminal.
+    fn sync_focus(&mut self) {
+        if !self.state.tabs.iter().any(|tab| tab.is_active) {
+            if let Some(tab) = self.state.tabs.first_mut() {
+                tab.is_active = true;
+            }
+        }
+        let focused = self
+            .state
+            .tabs
+            .iter()
+            .find(|tab| tab.is_active)
+            .map(|tab| tab.focused.clone());
+        for term in &mut self.state.terminals {
+            term.is_active = focused.as_ref() == Some(&term.id);
+            if term.is_active {
+                term.bell = false;
+            }
+        }
+    }
 
-        self.socket_agent.send(AgentInput::SendData(pingdata));
+    /// Forgets a terminal, closing its pane or, for the last pane, its tab.
+    fn drop_terminal(&mut self, termid: &str) {
+        self.state.terminals.retain(|term| term.id != termid);
+        if let Some(idx) = self.state.tabs.iter().position(|tab| tab.layout.contains(termid)) {
+            let tab = &mut self.state.tabs[idx];
+            if tab.layout == PaneNode::Leaf(termid.to_string()) {
+                self.state.tabs.remove(idx);
+            } else {
+                tab.layout.remove(termid);
+                if tab.focused == termid {
+                    tab.focused = tab.layout.panes()[0].clone();
+                }
+            }
+        }
+        self.sync_focus();
     }
 
-    fn send_data(&mut self, data: TransferData) {
-        self.socket_agent.send(AgentInput::SendData(data));
+    /// Creates or resumes the audio context, called from a click.
+    fn unlock_audio(&mut self) -> Result<(), JsValue> {
+        match &self.audio {
+            None => self.audio = Some(web_sys::AudioContext::new()?),
+            Some(context) if context.state() == web_sys::AudioContextState::Suspended => {
+                let _ = context.resume()?;
+            }
+            Some(_) => {}
+        }
+        Ok(())
     }
 
-    fn handle_data(&mut self, data: TransferData) {
-        match data.command.as_str() {
-            "ping" => {
-                let p: f64 = data.args[1].parse().expect("Not f64");
+    /// Short beep for a bell in a background terminal, silent until the user
+    /// has clicked somewhere.
+    fn beep(&mut self) -> Result<(), JsValue> {
+        let context = match &self.audio {
+            Some(context) if context.state() == web_sys::AudioContextState::Running => context,
+            _ => return Ok(()),
+        };
+        let oscillator = context.create_oscillator()?;
+        let gain = context.create_gain()?;
+        oscillator.frequency().set_value(880.0);
+        gain.gain().set_value(0.05);
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&context.destination())?;
+        oscillator.start()?;
+        oscillator.stop_with_when(context.current_time() + 0.1)?;
+        Ok(())
+    }
+
+    fn send_request(&mut self, request: Request) {
+        self.socket_agent.send(AgentInput::Send(request));
+    }
+
+    fn handle_response(&mut self, response: Response) {
+        match response {
+            Response::Pong(p) => {
                 let date = js_sys::Date::new_0();
                 let msec = date.get_time();
 
@@ -104,37 +307,67 @@ impl AppHome {
                 self.send_ping();
                 self.link.send_message(Msg::PingUpdate(msec - p))
             }
-            "process" => match data.value.as_str() {
-                "list" => {
-                    let mut to_remove = vec![];
-                    for k in self.state.terminals.keys() {
-                        if data.args.iter().position(|p| p == k).is_none() {
-                            to_remove.push(k.clone());
-                        }
+            Response::ProcessList(termids) if self.state.discovering => {
+                self.state.discovering = false;
+                for termid in termids.iter() {
+                    if self.terminal_mut(termid).is_none() {
+                        log::debug!("Reattaching term {}", termid);
+                        self.new_terminal(termid, String::new(), true);
+                        self.open_tab(termid, false);
                     }
-                    if !to_remove.is_empty() {
-                        log::debug!(
-                            "Removing terms {:?} received list {:?}",
-                            to_remove,
-                            data.args
-                        );
-                        for e in to_remove {
-                            self.state.terminals.remove(&e);
+                }
+                self.sync_focus();
+                match &self.project_config {
+                    Some(_) if self.state.terminals.is_empty() => {
+                        self.link.send_message(Msg::CreateTerm(String::new()));
+                    }
+                    Some(_) => {}
+                    None if !termids.is_empty() => {
+                        if let Some(project) = self.profiles.last_project(&self.meta.url) {
+                            self.link.send_message(Msg::OpenProject(project));
                         }
-                        self.link.send_message(Msg::Update);
                     }
+                    None => {}
                 }
-                _ => log::info!("Unknown process value {:?}", data),
-            },
-            "exec"=>{
-                // match self.state.terminals.g
-            },
-            "fs" => {
-
-            },
-            _cmd => {
-                log::info!("Unknown command {:#?}", data);
+                self.link.send_message(Msg::Update);
             }
+            Response::ProcessList(termids) if self.state.resuming => {
+                self.state.resuming = false;
+                let mut respawned = vec![];
+                for term in self.state.terminals.iter_mut() {
+                    if termids.iter().position(|p| p == &term.id).is_none() {
+                        term.generation += 1;
+                        respawned.push(term.id.clone());
+                    }
+                }
+                if !respawned.is_empty() {
+                    log::debug!("Respawning terms {:?} after reconnect", respawned);
+                    for termid in respawned {
+                        self.send_request(Request::ProcessNew(termid));
+                    }
+                    self.link.send_message(Msg::Update);
+                }
+            }
+            Response::ProcessList(termids) => {
+                let mut to_remove = vec![];
+                for term in &self.state.terminals {
+                    if termids.iter().position(|p| p == &term.id).is_none() {
+                        to_remove.push(term.id.clone());
+                    }
+                }
+                if !to_remove.is_empty() {
+                    log::debug!(
+                        "Removing terms {:?} received list {:?}",
+                        to_remove,
+                        termids
+                    );
+                    for termid in to_remove {
+                        self.drop_terminal(&termid);
+                    }
+                    self.link.send_message(Msg::Update);
+                }
+            }
+            _ => {}
         }
     }
 }
@@ -146,102 +379,279 @@ impl Component for AppHome {
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
         let propsclone = props.clone();
         let socket_agent = SocketAgent::bridge(link.callback(|data| match data {
-            AgentOutput::SocketMessage(message) => Msg::SocketMessage(message),
+            AgentOutput::Response(response) => Msg::SocketMessage(response),
+            AgentOutput::Connected(_) => Msg::StatusChange(ConnectionStatus::Connected),
+            AgentOutput::Reconnecting(attempt) => {
+                Msg::StatusChange(ConnectionStatus::Reconnecting(attempt))
+            }
+            AgentOutput::Disconnected => Msg::StatusChange(ConnectionStatus::Disconnected),
+            AgentOutput::AuthFailed(reason) => Msg::AuthFailed(reason),
             _ => Msg::Ignore,
         }));
         let adt = AppHome {
             meta: props.meta,
+            onauthfailed: props.onauthfailed,
+            profiles: ProfileStore::load(),
             link,
             state: ConnectedStateData::default(),
             project_config: None,
+            show_new_project: false,
             socket_agent,
+            pane_keys: None,
+            audio: None,
         };
         adt
     }
 
     fn mounted(&mut self) -> bool {
+        let link = self.link.clone();
+        let options = EventListenerOptions {
+            phase: EventListenerPhase::Capture,
+            passive: false,
+        };
+        self.pane_keys = Some(EventListener::new_with_options(
+            &yew::utils::window(),
+            "keydown",
+            options,
+            move |event| {
+                let key_event = event.dyn_ref::<KeyboardEvent>().expect("Not key event");
+                if !key_event.ctrl_key() || !key_event.shift_key() {
+                    return;
+                }
+                // Only while typing into a terminal pane; editors keep their own shortcuts.
+                let in_pane = event
+                    .target()
+                    .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+                    .and_then(|target| target.closest(".dcode-pane").ok().flatten())
+                    .is_some();
+                if !in_pane {
+                    return;
+                }
+                let direction = match key_event.key().as_str() {
+                    "ArrowLeft" => FocusDirection::Left,
+                    "ArrowRight" => FocusDirection::Right,
+                    "ArrowUp" => FocusDirection::Up,
+                    "ArrowDown" => FocusDirection::Down,
+                    _ => return,
+                };
+                event.prevent_default();
+                event.stop_propagation();
+                link.send_message(if key_event.alt_key() {
+                    Msg::ResizePane(direction)
+                } else {
+                    Msg::MoveFocus(direction)
+                });
+            },
+        ));
         self.send_ping();
+        // Shells keep running on the backend across page reloads.
+        self.state.discovering = true;
+        self.send_request(Request::ProcessList);
+        let default_project = self
+            .profiles
+            .profile_for_url(&self.meta.url)
+            .and_then(|profile| profile.default_project.clone());
+        if let Some(project) = default_project {
+            self.link.send_message(Msg::OpenProject(project));
+        }
         false
     }
 
     fn update(&mut self, msg: Self::Message) -> bool {
         match msg {
+            Msg::UnlockAudio => {
+                if let Err(err) = self.unlock_audio() {
+                    log::error!("Cannot start audio {:?}", err);
+                }
+                false
+            }
             Msg::PingUpdate(ping) => {
                 self.state.ping = ping;
                 true
             }
+            Msg::StatusChange(status) => {
+                if status == ConnectionStatus::Connected
+                    && self.state.status != ConnectionStatus::Connected
+                {
+                    // Terminals whose process did not survive are respawned once the list arrives.
+                    self.state.resuming = true;
+                    self.send_ping();
+                    self.send_request(Request::ProcessList);
+                }
+                self.state.status = status;
+                true
+            }
+            Msg::AuthFailed(reason) => {
+                self.state.status = ConnectionStatus::Disconnected;
+                self.onauthfailed.emit(reason);
+                true
+            }
             Msg::CreateProject(project) => {
                 log::info!("name {:#?}", project);
-                self.link.send_message(Msg::CreateTerm(format!("{}",project.config.init.replace("{}",&project.name))));
+                self.link.send_message(Msg::CreateTerm(project.init.clone()));
+                self.remember_project(&project.name);
                 self.project_config = Some(project);
+                self.show_new_project = false;
                 true
             }
-            Msg::CreateTerm(init_cmd) => {
-                let terms = self.state.terminals.len();
-                for term in &mut self.state.terminals {
-                    term.1.is_active = false;
+            Msg::OpenProject(name) => {
+                log::info!("open {}", name);
+                self.remember_project(&name);
+                self.project_config = Some(ProjectConfig {
+                    name,
+                    config: Template::default(),
+                    init: String::new(),
+                    panels: Panels {
+                        file_manager: true,
+                        terminal: true,
+                    },
+                });
+                // Reattached terminals take the place of a fresh one.
+                if !self.state.discovering && self.state.terminals.is_empty() {
+                    self.link.send_message(Msg::CreateTerm("".to_string()));
                 }
-                let termid = format!("term{}",terms+1);
+                true
+            }
+            Msg::ShowNewProject(show) => {
+                self.show_new_project = show;
+                true
+            }
+            Msg::CreateTerm(init_cmd) => {
+                let termid = self.allocate_term_id();
                 // log::debug!("creating term {}",termid);
-                self.state.terminals.insert(
-                     termid.clone(),
-                    TerminalHost {
-                        is_active: true,
-                        title: termid.clone(),
-                        init_dir: format!("/projects/{}",self.project_config.clone().unwrap_or_default().name),
-                        init_cmd
-                        // terminal: html!(<TerminalComp termid={termid.clone()} />),
-                    },
-                );
+                self.new_terminal(&termid, init_cmd, false);
+                self.open_tab(&termid, true);
+                self.sync_focus();
                 // log::debug!("created term {}",termid.clone());
 
 
-                self.send_data(TransferData {
-                    command: "process".to_string(),
-                    value: "new".to_string(),
-                    args: vec![format!("term{}", terms + 1)],
-                });
+                self.send_request(Request::ProcessNew(termid));
                 true
             }
-            Msg::SwitchTerminal(termid)=>{
-                let terms = self.state.terminals.len();
-                for term in &mut self.state.terminals {
-                    term.1.is_active = false;
+            Msg::SplitTerm(direction) => {
+                let focused = match self.state.tabs.iter().find(|tab| tab.is_active) {
+                    Some(tab) if tab.layout.can_split(&tab.focused, direction) => tab.focused.clone(),
+                    _ => return false,
+                };
+                let termid = self.allocate_term_id();
+                self.new_terminal(&termid, String::new(), false);
+                if let Some(tab) = self.active_tab_mut() {
+                    tab.layout.split(&focused, &termid, direction);
+                    tab.focused = termid.clone();
                 }
-                if let Some(term)=self.state.terminals.get_mut(&termid){
-                    term.is_active=true;
+                self.sync_focus();
+                self.send_request(Request::ProcessNew(termid));
+                true
+            }
+            Msg::FocusPane(termid) => {
+                let tabid = match self.state.tabs.iter_mut().find(|tab| tab.layout.contains(&termid)) {
+                    Some(tab) if tab.is_active && tab.focused == termid => return false,
+                    Some(tab) => {
+                        tab.focused = termid;
+                        tab.id.clone()
+                    }
+                    None => return false,
+                };
+                self.activate_tab(&tabid);
+                true
+            }
+            Msg::MoveFocus(direction) => {
+                let moved = match self.active_tab_mut() {
+                    Some(tab) => match tab.layout.neighbour(&tab.focused, direction) {
+                        Some(neighbour) => {
+                            tab.focused = neighbour;
+                            true
+                        }
+                        None => false,
+                    },
+                    None => false,
+                };
+                if moved {
+                    self.sync_focus();
                 }
-
+                moved
+            }
+            Msg::ResizePane(direction) => match self.active_tab_mut() {
+                Some(tab) => {
+                    let focused = tab.focused.clone();
+                    tab.layout.resize_towards(&focused, direction)
+                }
+                None => false,
+            },
+            Msg::SwitchTerminal(tabid)=>{
+                self.activate_tab(&tabid);
                 true
             }
             Msg::Update => true,
             Msg::Ignore => false,
-            Msg::SocketMessage(msg) => {
-                self.handle_data(msg);
+            Msg::SocketMessage(response) => {
+                self.handle_response(response);
                 false
             }
             Msg::RemoveTerm(termid) => {
-                self.state.terminals.remove(&termid);
-                let mut has_active = false;
-                for term in &self.state.terminals{
-                    if term.1.is_active{
-                        has_active=true;
-                        break;
+                self.drop_terminal(&termid);
+                self.send_request(Request::ProcessKill(termid));
+                true
+            }
+            Msg::Bell(termid) => {
+                let rang = match self.terminal_mut(&termid) {
+                    Some(term) if !term.is_active && !term.bell => {
+                        term.bell = true;
+                        true
                     }
-                }
-                if !has_active{
-                    if let Some(term)=self.state.terminals.iter_mut().next(){
-                        term.1.is_active=true;
+                    _ => false,
+                };
+                // Beep once per unseen bell rather than on every BEL byte.
+                if rang {
+                    if let Err(err) = self.beep() {
+                        log::error!("Cannot beep {:?}", err);
                     }
                 }
+                rang
+            }
+            Msg::CloseTab(tabid) => {
+                let panes = match self.state.tabs.iter().find(|tab| tab.id == tabid) {
+                    Some(tab) => tab.layout.panes(),
+                    None => return false,
+                };
+                for termid in panes {
+                    self.drop_terminal(&termid);
+                    self.send_request(Request::ProcessKill(termid));
+                }
                 true
             }
+            Msg::DragTerm(tabid) => {
+                self.state.dragged_tab = Some(tabid);
+                false
+            }
+            Msg::DropTerm(target) => {
+                let dragged = match self.state.dragged_tab.take() {
+                    Some(dragged) if dragged != target => dragged,
+                    _ => return false,
+                };
+                let from = self.state.tabs.iter().position(|tab| tab.id == dragged);
+                let to = self.state.tabs.iter().position(|tab| tab.id == target);
+                match (from, to) {
+                    (Some(from), Some(to)) => {
+                        // Dropped on a tab, the dragged one takes its place.
+                        let tab = self.state.tabs.remove(from);
+                        self.state.tabs.insert(to, tab);
+                        true
+                    }
+                    _ => false,
+                }
+            }
             Msg::RenameTerminal(termid,title) => {
-                if let Some(term) = self.state.terminals.get_mut(&termid){
+                if let Some(term) = self.terminal_mut(&termid){
                     term.title=title;
                 }
                 true
             }
+            Msg::SetScrollback(lines) => {
+                self.profiles.set_scrollback(lines);
+                self.profiles.save();
+                true
+            }
             Msg::SetPanels(panels)=>{
                 if let Some(config) = &mut self.project_config{
                     config.panels=panels;
@@ -323,18 +733,56 @@ impl Component for AppHome {
     fn view(&self) -> Html {
         let terminal_tabs = html!{
             <>
-                { for self.state.terminals.iter().map(|tab| {
-                      let title = tab.0.clone();
-                      let title1 = tab.0.clone();
+                { for self.state.tabs.iter().map(|tab| {
+                      let title = tab.id.clone();
+                      let title1 = tab.id.clone();
+                      let label = self
+                          .state
+                          .terminals
+                          .iter()
+                          .find(|term| term.id == tab.focused)
+                          .map(|term| term.title.clone())
+                          .unwrap_or_default();
+                      let panes = tab.layout.panes();
+                      let bell = self
+                          .state
+                          .terminals
+                          .iter()
+                          .any(|term| term.bell && panes.contains(&term.id));
+                      let drag_id = tab.id.clone();
+                      let drop_id = tab.id.clone();
                       html! {
-                          <li key=tab.0.clone() class={
-                              if tab.1.is_active{
+                          <li key=tab.id.clone() class={
+                              if tab.is_active{
                                   "is-active"
                               }else{
                                   ""
                               }
-                          }>
-                            <a onclick=self.link.callback(move |_|Msg::SwitchTerminal(title.clone()))>{tab.1.title.clone()} <span onclick=self.link.callback(move |_|Msg::RemoveTerm(title1.clone())) class="delete is-small"></span></a>
+                          } draggable="true"
+                            ondragstart=self.link.callback(move |e:DragEvent|{
+                                // Firefox only starts a drag that carries data.
+                                if let Some(data)=e.data_transfer(){
+                                    let _ = data.set_data("text/plain",&drag_id);
+                                }
+                                Msg::DragTerm(drag_id.clone())
+                            })
+                            ondragover=self.link.callback(|e:DragEvent|{
+                                e.prevent_default();
+                                Msg::Ignore
+                            })
+                            ondrop=self.link.callback(move |e:DragEvent|{
+                                e.prevent_default();
+                                Msg::DropTerm(drop_id.clone())
+                            })>
+                            <a onclick=self.link.callback(move |_|Msg::SwitchTerminal(title.clone()))>{
+                                if bell{
+                                    html!{
+                                        <span class="icon is-small has-text-warning"><i class="fas fa-bell"></i></span>
+                                    }
+                                }else{
+                                    html!{}
+                                }
+                            }{label} <span onclick=self.link.callback(move |_|Msg::CloseTab(title1.clone())) class="delete is-small"></span></a>
                           </li>
 
                       }
@@ -342,19 +790,42 @@ impl Component for AppHome {
                 }
             </>
         };
+        // Every terminal stays mounted in one flat list so splitting or
+        // switching tabs never restarts a session; the layout only moves them.
+        let panes = self
+            .state
+            .tabs
+            .iter()
+            .find(|tab| tab.is_active)
+            .map(|tab| tab.layout.layout(PaneRect::FULL))
+            .unwrap_or_default();
+        let focused_pane = self
+            .state
+            .tabs
+            .iter()
+            .find(|tab| tab.is_active)
+            .map(|tab| tab.focused.clone());
         let terminals = html!{
-            <div>
+            <div style="position:relative;height:40vh;">
             { for self.state.terminals.iter().map(|tab| {
-                let id = tab.0.clone();
+                let id = tab.id.clone();
+                let bell_id = tab.id.clone();
+                let focus_id = tab.id.clone();
+                let rect = panes.iter().find(|(termid,_)|termid==&tab.id).map(|(_,rect)|*rect);
+                let style = match rect{
+                    Some(rect) if tab.is_active && panes.len()>1=>rect.style()+"box-shadow:inset 0 0 0 1px #3273dc;",
+                    Some(rect)=>rect.style(),
+                    None=>String::new()
+                };
                   html! {
-                      <div key=tab.0.clone() class={
-                          if tab.1.is_active{
-                              ""
+                      <div key=tab.id.clone() class={
+                          if rect.is_some(){
+                              "dcode-pane"
                           }else{
-                              "is-hidden"
+                              "dcode-pane is-hidden"
                           }
-                      }>
-                          <TerminalComp  thost=tab.1.clone() title_change=self.link.callback(move |data:String|Msg::RenameTerminal(id.clone(),data.clone()))  termid=tab.1.title.clone()/>
+                      } style=style onmousedown=self.link.callback(move |_|Msg::FocusPane(focus_id.clone()))>
+                          <TerminalComp  thost=tab.clone() title_change=self.link.callback(move |data:String|Msg::RenameTerminal(id.clone(),data.clone())) on_bell=self.link.callback(move |_|Msg::Bell(bell_id.clone()))  termid=tab.id.clone() scrollback=self.profiles.scrollback()/>
                       </div>
                   }
                 })
@@ -373,7 +844,7 @@ impl Component for AppHome {
 
 
         html! {
-            <div>
+            <div onclick=self.link.callback(|_| Msg::UnlockAudio)>
 
                 <div class="level is-mobile">
                     <div class="level-left">
@@ -381,6 +852,20 @@ impl Component for AppHome {
                             <span class="tag">{"Ping"}</span>
                             <span class="tag is-primary">{self.state.ping}{"ms"}</span>
                         </div>
+                        <div class="tags has-addons level-item">
+                            <span class="tag">{"Server"}</span>
+                            {match self.state.status {
+                                ConnectionStatus::Connected => html!{
+                                    <span class="tag is-success">{"Connected"}</span>
+                                },
+                                ConnectionStatus::Reconnecting(attempt) => html!{
+                                    <span class="tag is-warning">{format!("Reconnecting ({})", attempt)}</span>
+                                },
+                                ConnectionStatus::Disconnected => html!{
+                                    <span class="tag is-danger">{"Disconnected"}</span>
+                                },
+                            }}
+                        </div>
                     </div>
                     <div class="level-right">
                         <div class="level-item">
@@ -487,7 +972,18 @@ impl Component for AppHome {
                                                                       "is-hidden"
                                                                   }
                                                               }>
-                                                                <Editor host=file.clone() file=file.file.clone() clean_callback=self.link.callback(|file|Msg::CleanStatus(file))/>
+                                                                {
+                                                                    // Terminal recordings open in the player rather than as text.
+                                                                    if file.file.name.ends_with(".cast"){
+                                                                        html!{
+                                                                            <AsciicastPlayer path=file.file.path.clone()/>
+                                                                        }
+                                                                    }else{
+                                                                        html!{
+                                                                            <Editor host=file.clone() file=file.file.clone() clean_callback=self.link.callback(|file|Msg::CleanStatus(file))/>
+                                                                        }
+                                                                    }
+                                                                }
                                                               </div>
 
                                                           }
@@ -512,10 +1008,33 @@ impl Component for AppHome {
                                                         </div>
                                                     </div>
                                                     <div class="level-right">
+                                                        <div class="level-item">
+                                                            <input class="input" type="number" min="0" style="width:7em;" title="Scrollback lines of new terminals" value=self.profiles.scrollback().to_string()
+                                                                onchange=self.link.callback(|e:ChangeData|match e{
+                                                                    ChangeData::Value(value)=>value.parse().map(Msg::SetScrollback).unwrap_or(Msg::Ignore),
+                                                                    _=>Msg::Ignore
+                                                                })/>
+                                                        </div>
                                                         <div class="level-item field has-addons">
                                                             <button onclick=self.link.callback(|_|Msg::CreateTerm("".to_string())) class="button">
                                                                 {"+"}
                                                             </button>
+                                                            <button onclick=self.link.callback(|_|Msg::SplitTerm(SplitDirection::Horizontal)) class="button" title="Split side by side">
+                                                                <span class="icon is-small"><i class="fas fa-columns"></i></span>
+                                                            </button>
+                                                            <button onclick=self.link.callback(|_|Msg::SplitTerm(SplitDirection::Vertical)) class="button" title="Split top and bottom">
+                                                                <span class="icon is-small"><i class="fas fa-grip-lines"></i></span>
+                                                            </button>
+                                                            {
+                                                                match focused_pane.clone(){
+                                                                    Some(termid) if panes.len()>1=>html!{
+                                                                        <button onclick=self.link.callback(move |_|Msg::RemoveTerm(termid.clone())) class="button" title="Close pane">
+                                                                            <span class="icon is-small"><i class="fas fa-times"></i></span>
+                                                                        </button>
+                                                                    },
+                                                                    _=>html!{}
+                                                                }
+                                                            }
                                                         </div>
                                                     </div>
                                                 </div>
@@ -529,7 +1048,25 @@ impl Component for AppHome {
                         }
                         None => {
                             html!(
-                                <NewProjectDialog oncreate=self.link.callback(|config|Msg::CreateProject(config))/>
+                                <div>
+                                    <ProjectPicker
+                                        last_project=self.profiles.last_project(&self.meta.url)
+                                        onopen=self.link.callback(|name|Msg::OpenProject(name))
+                                        onnew=self.link.callback(|_|Msg::ShowNewProject(true))
+                                    />
+                                    {
+                                        if self.show_new_project {
+                                            html!(
+                                                <NewProjectDialog
+                                                    oncreate=self.link.callback(|config|Msg::CreateProject(config))
+                                                    oncancel=self.link.callback(|_|Msg::ShowNewProject(false))
+                                                />
+                                            )
+                                        } else {
+                                            html!()
+                                        }
+                                    }
+                                </div>
                             )
                         }
                     }
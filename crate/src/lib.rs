@@ -19,6 +19,11 @@ mod file_manager;
 mod editor;
 mod editor_src;
 mod new_file;
+mod profiles;
+mod project_picker;
+mod pane_layout;
+mod recording;
+mod asciicast_player;
 
 use app::App;
 
@@ -1,37 +1,209 @@
 use crate::ide_home::Panels;
 use yew::prelude::*;
+use yew::format::Json;
+use yew::services::storage::{Area,StorageService};
 use std::collections::HashMap;
-use maplit::hashmap;
+use serde::{Serialize,Deserialize};
 
+use super::project_picker::PROJECTS_DIR;
+use super::socket_agent::{AgentInput,AgentOutput,Request,Response,SocketAgent};
+
+const LOCAL_TEMPLATES_KEY:&str = "dcode.templates";
+const WORKSPACE_TEMPLATES:&str = "/projects/.dcode/templates.json";
 
 #[derive(Debug,Clone,Default,PartialEq)]
 pub struct ProjectConfig{
     pub name:String,
     pub config:Template,
+    /// Rendered `config.init`, empty when opening an existing project.
+    pub init:String,
     pub panels:Panels
 }
 
-#[derive(Clone,Debug,Default,PartialEq)]
+/// A project template as found in a manifest:
+/// `{"templates":[{"name":"..","description":"..","init":"..","params":[..]}]}`.
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq)]
 pub struct Template{
     pub name:String,
-    pub init:String
+    #[serde(default)]
+    pub description:String,
+    /// Shell command run from `/`. `{project}` is replaced by the validated
+    /// project name and `{<param>}` by the shell-quoted parameter value.
+    pub init:String,
+    #[serde(default)]
+    pub params:Vec<TemplateParam>
+}
+
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq)]
+pub struct TemplateParam{
+    pub name:String,
+    #[serde(default)]
+    pub label:String,
+    /// Parameters without a default must be filled in.
+    #[serde(default)]
+    pub default:Option<String>
+}
+
+#[derive(Serialize,Deserialize,Debug,Default)]
+pub struct TemplateManifest{
+    pub templates:Vec<Template>
+}
+
+pub fn validate_project_name(name:&str)->Result<(),String>{
+    if name.is_empty(){
+        return Err("Enter a project name".to_string());
+    }
+    if name.len()>64{
+        return Err("Project name is too long".to_string());
+    }
+    if name.starts_with('.') || name.starts_with('-'){
+        return Err("Project name cannot start with '.' or '-'".to_string());
+    }
+    if !name.chars().all(|c|c.is_ascii_alphanumeric() || c=='-' || c=='_' || c=='.'){
+        return Err("Project name may only contain letters, digits, '-', '_' and '.'".to_string());
+    }
+    Ok(())
+}
+
+fn shell_quote(value:&str)->String{
+    format!("'{}'",value.replace('\'',"'\\''"))
+}
+
+impl Template{
+    pub fn render(&self,project:&str,values:&HashMap<String,String>)->Result<String,String>{
+        validate_project_name(project)?;
+        let mut quoted = HashMap::new();
+        for param in &self.params{
+            let value = values.get(&param.name)
+                .filter(|value|!value.is_empty())
+                .or(param.default.as_ref());
+            match value{
+                Some(value)=>{
+                    quoted.insert(param.name.as_str(),shell_quote(value));
+                }
+                None=>return Err(format!("{} is required",param.label_or_name()))
+            }
+        }
+        // A single pass over the template, so a value containing `{other}`
+        // is never substituted again. Unknown `{..}` are left as they are.
+        let mut init = String::with_capacity(self.init.len());
+        let mut rest = self.init.as_str();
+        while let Some(start)=rest.find('{'){
+            init.push_str(&rest[..start]);
+            rest=&rest[start..];
+            let name = rest[1..].find('}').map(|end|&rest[1..end+1]);
+            let value = match name{
+                // `{}` is the placeholder of the original single-parameter templates.
+                Some("project") | Some("")=>Some(project),
+                Some(name)=>quoted.get(name).map(String::as_str),
+                None=>None
+            };
+            match (name,value){
+                (Some(name),Some(value))=>{
+                    init.push_str(value);
+                    rest=&rest[name.len()+2..];
+                }
+                _=>{
+                    init.push('{');
+                    rest=&rest[1..];
+                }
+            }
+        }
+        init.push_str(rest);
+        Ok(init)
+    }
+
+    fn defaults(&self)->HashMap<String,String>{
+        self.params.iter()
+            .map(|param|(param.name.clone(),param.default.clone().unwrap_or_default()))
+            .collect()
+    }
+}
+
+impl TemplateParam{
+    fn label_or_name(&self)->&str{
+        if self.label.is_empty(){
+            &self.name
+        }else{
+            &self.label
+        }
+    }
+}
+
+fn builtin_templates()->Vec<Template>{
+    vec![
+        Template{
+            name:"blank".to_string(),
+            description:"Empty directory".to_string(),
+            init:format!("mkdir -p {0}/{{project}} && cd {0}/{{project}}",PROJECTS_DIR),
+            params:vec![]
+        },
+        Template{
+            name:"react".to_string(),
+            description:"React app generated by create-react-app".to_string(),
+            init:format!("mkdir -p {0}/{{project}} && cd {0}/{{project}} && yarn create react-app . --template {{template}}",PROJECTS_DIR),
+            params:vec![
+                TemplateParam{
+                    name:"template".to_string(),
+                    label:"CRA template".to_string(),
+                    default:Some("cra-template".to_string())
+                }
+            ]
+        }
+    ]
+}
+
+fn local_templates()->Vec<Template>{
+    match StorageService::new(Area::Local){
+        Ok(storage)=>{
+            let Json(manifest):Json<Result<TemplateManifest,_>> = storage.restore(LOCAL_TEMPLATES_KEY);
+            manifest.map(|manifest|manifest.templates).unwrap_or_default()
+        }
+        Err(_)=>vec![]
+    }
 }
 
 pub struct NewProjectDialog{
     pub props:Props,
     link:ComponentLink<Self>,
-    name_ref:NodeRef,
-    select_ref:NodeRef,
-    templates:HashMap<String,Template>
+    agent_bridge:Box<dyn yew::Bridge<SocketAgent>>,
+    templates:Vec<Template>,
+    selected:String,
+    name:String,
+    params:HashMap<String,String>,
+    error:Option<String>
 }
 
 #[derive(Clone,Properties)]
 pub struct Props{
-    pub oncreate:Callback<ProjectConfig>
+    pub oncreate:Callback<ProjectConfig>,
+    pub oncancel:Callback<()>
 }
 
 pub enum Msg{
-    Create
+    Create,
+    Cancel,
+    Select(String),
+    SetName(String),
+    SetParam(String,String),
+    WorkspaceManifest(String),
+    Ignore
+}
+
+impl NewProjectDialog{
+    /// Adds templates, replacing built-in or earlier ones with the same name.
+    fn merge(&mut self,templates:Vec<Template>){
+        for template in templates{
+            match self.templates.iter_mut().find(|t|t.name==template.name){
+                Some(existing)=>*existing=template,
+                None=>self.templates.push(template)
+            }
+        }
+    }
+
+    fn selected_template(&self)->Option<&Template>{
+        self.templates.iter().find(|template|template.name==self.selected)
+    }
 }
 
 impl Component for NewProjectDialog{
@@ -40,61 +212,101 @@ impl Component for NewProjectDialog{
     type Properties = Props;
 
     fn create(props:Props, link:ComponentLink<Self>) -> Self {
+        let mut agent_bridge = SocketAgent::bridge(link.callback(|data|match data{
+            AgentOutput::Reply(_,Ok(Response::FsOpen{content,..}))=>Msg::WorkspaceManifest(content),
+            _=>Msg::Ignore
+        }));
+        agent_bridge.send(AgentInput::Call(Request::FsOpen(WORKSPACE_TEMPLATES.to_string())));
 
-        let templates = hashmap!{
-            "blank".to_string() => Template{
-                name:"blank".to_string(),
-                init:"mkdir -p /projects/{} && cd /projects/{}".to_string()
-            },
-            "react".to_string() => Template{
-                name:"react".to_string(),
-                init:"mkdir -p /projects/{} && cd /projects/{} && yarn create react-app .".to_string()
-            }
-        };
-
-        NewProjectDialog{
+        let mut dialog = NewProjectDialog{
             props,
             link,
-            name_ref:NodeRef::default(),
-            select_ref:NodeRef::default(),
-            templates
+            agent_bridge,
+            templates:builtin_templates(),
+            selected:String::new(),
+            name:String::new(),
+            params:HashMap::new(),
+            error:None
+        };
+        dialog.merge(local_templates());
+        if let Some(first)=dialog.templates.first().cloned(){
+            dialog.selected=first.name.clone();
+            dialog.params=first.defaults();
         }
+        dialog
     }
+
     fn update(&mut self, msg: Msg) -> bool {
 
         match msg{
             Msg::Create=>{
-                let inpel:web_sys::HtmlInputElement = self.name_ref.cast().expect("not inputelement");
-                let selectel:web_sys::HtmlSelectElement = self.select_ref.cast().expect("not select element");
-                if !inpel.value().is_empty() && !selectel.value().is_empty(){
-                    self.props.oncreate.emit(
-                        ProjectConfig{
-                            name:inpel.value(),
-                            config:self.templates[&selectel.value()].clone(),
-                            panels:Panels{
-                                file_manager:false,
-                                terminal:true
+                let template = match self.selected_template(){
+                    Some(template)=>template.clone(),
+                    None=>return false
+                };
+                let name = self.name.trim().to_string();
+                match template.render(&name,&self.params){
+                    Ok(init)=>{
+                        self.error=None;
+                        self.props.oncreate.emit(
+                            ProjectConfig{
+                                name,
+                                config:template,
+                                init,
+                                panels:Panels{
+                                    file_manager:false,
+                                    terminal:true
+                                }
                             }
-                        }
-                    )
+                        )
+                    }
+                    Err(err)=>{
+                        self.error=Some(err);
+                        return true;
+                    }
+                }
+                false
+            }
+            Msg::Cancel=>{
+                self.props.oncancel.emit(());
+                false
+            }
+            Msg::Select(name)=>{
+                self.selected=name;
+                self.params=self.selected_template().map(|template|template.defaults()).unwrap_or_default();
+                true
+            }
+            Msg::SetName(name)=>{
+                self.name=name;
+                self.error=None;
+                true
+            }
+            Msg::SetParam(name,value)=>{
+                self.params.insert(name,value);
+                false
+            }
+            Msg::WorkspaceManifest(content)=>{
+                match serde_json::from_str::<TemplateManifest>(&content){
+                    Ok(manifest)=>{
+                        self.merge(manifest.templates);
+                        true
+                    }
+                    Err(err)=>{
+                        log::error!("Invalid {} {}",WORKSPACE_TEMPLATES,err);
+                        false
+                    }
                 }
             }
+            Msg::Ignore=>false
         }
-
-        false
     }
     fn view(&self) -> Html {
 
-
-        let mut lists = html!();
-        for temp in &self.templates{
-            lists = html!(
-                <>
-                    {lists}
-                    <option value={&temp.1.name}>{&temp.1.name}</option>
-                </>
-            )
-        }
+        let name_error = if self.name.is_empty(){
+            None
+        }else{
+            validate_project_name(self.name.trim()).err()
+        };
         html!{
             <div class="modal is-active">
               <div class="modal-background"></div>
@@ -105,22 +317,162 @@ impl Component for NewProjectDialog{
                 <section class="modal-card-body">
                     <div class="field">
                         <div class="control is-expanded">
-                            <input ref=self.name_ref.clone() class="input" type="text" placeholder="Project Name"></input>
+                            <input class={
+                                if name_error.is_some(){
+                                    "input is-danger"
+                                }else{
+                                    "input"
+                                }
+                            } type="text" placeholder="Project Name" value=&self.name oninput=self.link.callback(|e:InputData|Msg::SetName(e.value))></input>
                         </div>
+                        {
+                            match &name_error{
+                                Some(err)=>html!{ <p class="help is-danger">{err}</p> },
+                                None=>html!{}
+                            }
+                        }
+                    </div>
+                    <div class="field">
                         <div class="select">
-                            <select ref=self.select_ref.clone()>
-                                {
-                                    lists
-                                }
+                            <select onchange=self.link.callback(|e:ChangeData|match e{
+                                ChangeData::Select(el)=>Msg::Select(el.value()),
+                                _=>Msg::Ignore
+                            })>
+                                { for self.templates.iter().map(|template| html!{
+                                    <option value=&template.name selected=template.name==self.selected>{&template.name}</option>
+                                })}
                             </select>
                         </div>
+                        {
+                            match self.selected_template(){
+                                Some(template) if !template.description.is_empty()=>html!{
+                                    <p class="help">{&template.description}</p>
+                                },
+                                _=>html!{}
+                            }
+                        }
                     </div>
+                    { for self.selected_template().into_iter().flat_map(|template|template.params.iter()).map(|param| {
+                        let name = param.name.clone();
+                        html!{
+                            <div class="field" key=format!("{}-{}",self.selected,param.name)>
+                                <label class="label is-small">{param.label_or_name()}</label>
+                                <div class="control">
+                                    <input class="input is-small" type="text"
+                                        value=self.params.get(&param.name).cloned().unwrap_or_default()
+                                        oninput=self.link.callback(move |e:InputData|Msg::SetParam(name.clone(),e.value))></input>
+                                </div>
+                            </div>
+                        }
+                    })}
+                    {
+                        match &self.error{
+                            Some(err)=>html!{ <p class="help is-danger">{err}</p> },
+                            None=>html!{}
+                        }
+                    }
                 </section>
                 <footer class="modal-card-foot">
-                  <button class="button is-success" onclick=self.link.callback(|_|Msg::Create)>{"Create"}</button>
+                  <button class="button" onclick=self.link.callback(|_|Msg::Cancel)>{"Cancel"}</button>
+                  <button class="button is-success" disabled=name_error.is_some() onclick=self.link.callback(|_|Msg::Create)>{"Create"}</button>
                 </footer>
               </div>
             </div>
         }
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn new_template(init:&str,params:&[(&str,Option<&str>)])->Template{
+        Template{
+            name:"test".to_string(),
+            description:String::new(),
+            init:init.to_string(),
+            params:params.iter().map(|(name,default)|TemplateParam{
+                name:name.to_string(),
+                label:String::new(),
+                default:default.map(str::to_string)
+            }).collect()
+        }
+    }
+
+    fn values(pairs:&[(&str,&str)])->HashMap<String,String>{
+        pairs.iter().map(|(name,value)|(name.to_string(),value.to_string())).collect()
+    }
+
+    #[test]
+    fn render_substitutes_project_and_quoted_params(){
+        let template = new_template("mkdir {project} && cd {} && init {kind}",&[("kind",None)]);
+        assert_eq!(
+            template.render("demo",&values(&[("kind","web app")])),
+            Ok("mkdir demo && cd demo && init 'web app'".to_string())
+        );
+    }
+
+    #[test]
+    fn render_escapes_single_quotes(){
+        let template = new_template("echo {msg}",&[("msg",None)]);
+        assert_eq!(
+            template.render("demo",&values(&[("msg","it's")])),
+            Ok("echo 'it'\\''s'".to_string())
+        );
+    }
+
+    #[test]
+    fn render_does_not_expand_placeholders_inside_values(){
+        let template = new_template("run {a} {b}",&[("a",None),("b",None)]);
+        assert_eq!(
+            template.render("demo",&values(&[("a","{b}"),("b","'; rm -rf ~'")])),
+            Ok("run '{b}' ''\\''; rm -rf ~'\\'''".to_string())
+        );
+        let with_project = new_template("run {a} {project}",&[("a",None)]);
+        assert_eq!(
+            with_project.render("demo",&values(&[("a","{project}")])),
+            Ok("run '{project}' demo".to_string())
+        );
+    }
+
+    #[test]
+    fn render_uses_defaults_and_requires_the_rest(){
+        let template = new_template("init {kind} {name}",&[("kind",Some("basic")),("name",None)]);
+        assert_eq!(
+            template.render("demo",&values(&[("kind",""),("name","x")])),
+            Ok("init 'basic' 'x'".to_string())
+        );
+        assert_eq!(
+            template.render("demo",&values(&[])),
+            Err("name is required".to_string())
+        );
+    }
+
+    #[test]
+    fn render_keeps_unknown_braces(){
+        let template = new_template("touch {a,b} {x {project}",&[]);
+        assert_eq!(template.render("demo",&values(&[])),Ok("touch {a,b} {x demo".to_string()));
+    }
+
+    #[test]
+    fn render_rejects_invalid_project_names(){
+        let template = new_template("mkdir {project}",&[]);
+        assert!(template.render("a b",&values(&[])).is_err());
+        assert!(template.render("$(reboot)",&values(&[])).is_err());
+    }
+
+    #[test]
+    fn validate_project_name_accepts_plain_names(){
+        for name in &["demo","my-app","my_app.v2","A1"]{
+            assert_eq!(validate_project_name(name),Ok(()),"{}",name);
+        }
+    }
+
+    #[test]
+    fn validate_project_name_rejects_unsafe_names(){
+        let too_long = "a".repeat(65);
+        for name in &["","..",".hidden","-rf","a/b","a b","x;y","naïve",too_long.as_str()]{
+            assert!(validate_project_name(name).is_err(),"{}",name);
+        }
+    }
+}
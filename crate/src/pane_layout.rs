@@ -0,0 +1,384 @@
+//! Layout tree of the split panes inside one terminal tab.
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum SplitDirection{
+    /// Children side by side.
+    Horizontal,
+    /// Children stacked on top of each other.
+    Vertical
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FocusDirection{
+    Left,
+    Right,
+    Up,
+    Down
+}
+
+impl FocusDirection{
+    fn axis(self)->SplitDirection{
+        match self{
+            FocusDirection::Left|FocusDirection::Right=>SplitDirection::Horizontal,
+            FocusDirection::Up|FocusDirection::Down=>SplitDirection::Vertical
+        }
+    }
+}
+
+/// Position of a pane in percent of the panel.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct PaneRect{
+    pub x:f64,
+    pub y:f64,
+    pub width:f64,
+    pub height:f64
+}
+
+impl PaneRect{
+    pub const FULL:PaneRect = PaneRect{x:0.0,y:0.0,width:100.0,height:100.0};
+
+    pub fn style(&self)->String{
+        format!("position:absolute;left:{}%;top:{}%;width:{}%;height:{}%;",self.x,self.y,self.width,self.height)
+    }
+
+    fn center(&self)->(f64,f64){
+        (self.x+self.width/2.0,self.y+self.height/2.0)
+    }
+}
+
+/// Smallest share a pane can be resized to, and the smallest part of the
+/// panel a split may leave a pane with.
+const MIN_SHARE:f64 = 0.1;
+/// Rounding slack when comparing pane edges.
+const EDGE_SLACK:f64 = 0.01;
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum PaneNode{
+    /// A pane showing the terminal with this id.
+    Leaf(String),
+    /// Children with their share of the space, the shares adding up to 1.
+    Split{
+        direction:SplitDirection,
+        children:Vec<(f64,PaneNode)>
+    }
+}
+
+impl PaneNode{
+    pub fn contains(&self,id:&str)->bool{
+        match self{
+            PaneNode::Leaf(leaf)=>leaf==id,
+            PaneNode::Split{children,..}=>children.iter().any(|(_,child)|child.contains(id))
+        }
+    }
+
+    /// Terminal ids in layout order.
+    pub fn panes(&self)->Vec<String>{
+        match self{
+            PaneNode::Leaf(leaf)=>vec![leaf.clone()],
+            PaneNode::Split{children,..}=>children.iter().flat_map(|(_,child)|child.panes()).collect()
+        }
+    }
+
+    fn is_leaf(&self,id:&str)->bool{
+        match self{
+            PaneNode::Leaf(leaf)=>leaf==id,
+            _=>false
+        }
+    }
+
+    /// Whether `target` is large enough along `direction` to be halved.
+    pub fn can_split(&self,target:&str,direction:SplitDirection)->bool{
+        let rect = match self.layout(PaneRect::FULL).into_iter().find(|(id,_)|id==target){
+            Some((_,rect))=>rect,
+            None=>return false
+        };
+        let extent = match direction{
+            SplitDirection::Horizontal=>rect.width/PaneRect::FULL.width,
+            SplitDirection::Vertical=>rect.height/PaneRect::FULL.height
+        };
+        extent/2.0>=MIN_SHARE
+    }
+
+    /// Splits the pane `target` in two, `new` taking the second half.
+    /// Refused when the halves would be smaller than `MIN_SHARE` of the panel.
+    pub fn split(&mut self,target:&str,new:&str,direction:SplitDirection)->bool{
+        self.can_split(target,direction) && self.split_leaf(target,new,direction)
+    }
+
+    fn split_leaf(&mut self,target:&str,new:&str,direction:SplitDirection)->bool{
+        match self{
+            PaneNode::Leaf(leaf) if leaf==target=>{
+                *self=PaneNode::Split{
+                    direction,
+                    children:vec![(0.5,PaneNode::Leaf(target.to_string())),(0.5,PaneNode::Leaf(new.to_string()))]
+                };
+                true
+            }
+            PaneNode::Leaf(_)=>false,
+            PaneNode::Split{direction:current,children}=>{
+                // Splitting along the same axis adds a sibling instead of nesting.
+                if *current==direction{
+                    if let Some(idx)=children.iter().position(|(_,child)|child.is_leaf(target)){
+                        let share = children[idx].0/2.0;
+                        children[idx].0=share;
+                        children.insert(idx+1,(share,PaneNode::Leaf(new.to_string())));
+                        return true;
+                    }
+                }
+                children.iter_mut().any(|(_,child)|child.split_leaf(target,new,direction))
+            }
+        }
+    }
+
+    /// Removes the pane `target`, giving its space to its siblings. The root
+    /// pane itself cannot be removed; drop the whole tree instead.
+    pub fn remove(&mut self,target:&str)->bool{
+        let children = match self{
+            PaneNode::Split{children,..}=>children,
+            PaneNode::Leaf(_)=>return false
+        };
+        match children.iter().position(|(_,child)|child.is_leaf(target)){
+            Some(idx)=>{
+                let (share,_) = children.remove(idx);
+                let rest:f64 = children.iter().map(|(share,_)|share).sum();
+                for child in children.iter_mut(){
+                    child.0+=share*child.0/rest;
+                }
+                if children.len()==1{
+                    let (_,only) = children.remove(0);
+                    *self=only;
+                }
+                true
+            }
+            None=>children.iter_mut().any(|(_,child)|child.remove(target))
+        }
+    }
+
+    /// Grows (positive `delta`) or shrinks the pane `target` along `axis`,
+    /// taking the space from its next sibling, or the previous one for the
+    /// last pane. The innermost split along that axis is used. Neither pane
+    /// is taken below `MIN_SHARE`; one already there is left alone.
+    pub fn resize(&mut self,target:&str,axis:SplitDirection,delta:f64)->bool{
+        let (direction,children) = match self{
+            PaneNode::Split{direction,children}=>(*direction,children),
+            PaneNode::Leaf(_)=>return false
+        };
+        let idx = match children.iter().position(|(_,child)|child.contains(target)){
+            Some(idx)=>idx,
+            None=>return false
+        };
+        if children[idx].1.resize(target,axis,delta){
+            return true;
+        }
+        if direction!=axis{
+            return false;
+        }
+        let other = if idx+1<children.len() { idx+1 } else { idx-1 };
+        let amount = if delta<0.0{
+            delta.max((MIN_SHARE-children[idx].0).min(0.0))
+        }else{
+            delta.min((children[other].0-MIN_SHARE).max(0.0))
+        };
+        children[idx].0+=amount;
+        children[other].0-=amount;
+        true
+    }
+
+    pub fn layout(&self,rect:PaneRect)->Vec<(String,PaneRect)>{
+        match self{
+            PaneNode::Leaf(leaf)=>vec![(leaf.clone(),rect)],
+            PaneNode::Split{direction,children}=>{
+                let mut panes = vec![];
+                let mut offset = 0.0;
+                for (share,child) in children{
+                    let child_rect = match direction{
+                        SplitDirection::Horizontal=>PaneRect{
+                            x:rect.x+offset*rect.width,
+                            width:share*rect.width,
+                            ..rect
+                        },
+                        SplitDirection::Vertical=>PaneRect{
+                            y:rect.y+offset*rect.height,
+                            height:share*rect.height,
+                            ..rect
+                        }
+                    };
+                    offset+=share;
+                    panes.extend(child.layout(child_rect));
+                }
+                panes
+            }
+        }
+    }
+
+    /// Closest pane in `direction` from `from` that lines up with it.
+    pub fn neighbour(&self,from:&str,direction:FocusDirection)->Option<String>{
+        let panes = self.layout(PaneRect::FULL);
+        let (_,rect) = panes.iter().find(|(id,_)|id==from)?.clone();
+        let overlaps = |start:f64,len:f64,other_start:f64,other_len:f64|{
+            start<other_start+other_len-EDGE_SLACK && other_start<start+len-EDGE_SLACK
+        };
+        let center = rect.center();
+        panes.iter()
+            .filter(|(id,_)|id!=from)
+            .filter(|(_,other)|match direction{
+                FocusDirection::Left=>other.x+other.width<=rect.x+EDGE_SLACK && overlaps(rect.y,rect.height,other.y,other.height),
+                FocusDirection::Right=>other.x>=rect.x+rect.width-EDGE_SLACK && overlaps(rect.y,rect.height,other.y,other.height),
+                FocusDirection::Up=>other.y+other.height<=rect.y+EDGE_SLACK && overlaps(rect.x,rect.width,other.x,other.width),
+                FocusDirection::Down=>other.y>=rect.y+rect.height-EDGE_SLACK && overlaps(rect.x,rect.width,other.x,other.width)
+            })
+            .map(|(id,other)|{
+                let (x,y) = other.center();
+                (id,(x-center.0).powi(2)+(y-center.1).powi(2))
+            })
+            .min_by(|a,b|a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(id,_)|id.clone())
+    }
+
+    /// Resizes `target` one step towards `direction`: Right and Down grow it,
+    /// Left and Up shrink it.
+    pub fn resize_towards(&mut self,target:&str,direction:FocusDirection)->bool{
+        let delta = match direction{
+            FocusDirection::Right|FocusDirection::Down=>0.05,
+            FocusDirection::Left|FocusDirection::Up=>-0.05
+        };
+        self.resize(target,direction.axis(),delta)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn shares(node:&PaneNode)->Vec<f64>{
+        match node{
+            PaneNode::Split{children,..}=>children.iter().map(|(share,_)|*share).collect(),
+            PaneNode::Leaf(_)=>vec![]
+        }
+    }
+
+    fn assert_shares(node:&PaneNode,expected:&[f64]){
+        let actual = shares(node);
+        assert_eq!(actual.len(),expected.len(),"{:?}",actual);
+        for (a,e) in actual.iter().zip(expected){
+            assert!((a-e).abs()<1e-9,"{:?} != {:?}",actual,expected);
+        }
+    }
+
+    #[test]
+    fn split_adds_siblings_along_the_same_axis(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        assert!(root.split("a","b",SplitDirection::Horizontal));
+        assert!(root.split("a","c",SplitDirection::Horizontal));
+        assert_eq!(root.panes(),vec!["a","c","b"]);
+        assert_shares(&root,&[0.25,0.25,0.5]);
+    }
+
+    #[test]
+    fn split_nests_across_axes(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        root.split("a","b",SplitDirection::Horizontal);
+        root.split("b","c",SplitDirection::Vertical);
+        let layout = root.layout(PaneRect::FULL);
+        assert_eq!(layout[0],("a".to_string(),PaneRect{x:0.0,y:0.0,width:50.0,height:100.0}));
+        assert_eq!(layout[1],("b".to_string(),PaneRect{x:50.0,y:0.0,width:50.0,height:50.0}));
+        assert_eq!(layout[2],("c".to_string(),PaneRect{x:50.0,y:50.0,width:50.0,height:50.0}));
+    }
+
+    #[test]
+    fn split_refuses_panes_below_the_minimum(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        assert!(root.split("a","b",SplitDirection::Horizontal));
+        assert!(root.split("a","c",SplitDirection::Horizontal));
+        assert!(root.split("a","d",SplitDirection::Horizontal));
+        assert!(!root.can_split("a",SplitDirection::Horizontal));
+        assert!(!root.split("a","e",SplitDirection::Horizontal));
+        assert!(!root.contains("e"));
+        // The other axis still has room.
+        assert!(root.split("a","e",SplitDirection::Vertical));
+        assert!(!root.split("missing","f",SplitDirection::Vertical));
+    }
+
+    #[test]
+    fn remove_gives_space_to_siblings_and_collapses(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        root.split("a","b",SplitDirection::Horizontal);
+        root.split("b","c",SplitDirection::Horizontal);
+        assert!(root.remove("c"));
+        assert_shares(&root,&[2.0/3.0,1.0/3.0]);
+        assert!(root.remove("b"));
+        assert_eq!(root,PaneNode::Leaf("a".to_string()));
+        assert!(!root.remove("a"));
+    }
+
+    #[test]
+    fn resize_moves_space_between_neighbours(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        root.split("a","b",SplitDirection::Horizontal);
+        assert!(root.resize("a",SplitDirection::Horizontal,0.1));
+        assert_shares(&root,&[0.6,0.4]);
+        // The last pane trades with the previous one.
+        assert!(root.resize("b",SplitDirection::Horizontal,0.1));
+        assert_shares(&root,&[0.5,0.5]);
+        assert!(!root.resize("a",SplitDirection::Vertical,0.1));
+    }
+
+    #[test]
+    fn resize_clamps_to_the_minimum(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        root.split("a","b",SplitDirection::Horizontal);
+        root.resize("a",SplitDirection::Horizontal,-1.0);
+        assert_shares(&root,&[MIN_SHARE,1.0-MIN_SHARE]);
+        root.resize("a",SplitDirection::Horizontal,1.0);
+        assert_shares(&root,&[1.0-MIN_SHARE,MIN_SHARE]);
+    }
+
+    #[test]
+    fn resize_never_flips_direction_below_the_minimum(){
+        let mut root = PaneNode::Split{
+            direction:SplitDirection::Horizontal,
+            children:vec![
+                (0.05,PaneNode::Leaf("a".to_string())),
+                (0.9,PaneNode::Leaf("b".to_string())),
+                (0.05,PaneNode::Leaf("c".to_string()))
+            ]
+        };
+        // Shrinking a pane already below the minimum leaves it alone.
+        root.resize("a",SplitDirection::Horizontal,-0.05);
+        assert_shares(&root,&[0.05,0.9,0.05]);
+        // Growing it still works.
+        root.resize("a",SplitDirection::Horizontal,0.05);
+        assert_shares(&root,&[0.1,0.85,0.05]);
+        // Growing into a neighbour below the minimum does nothing.
+        root.resize("b",SplitDirection::Horizontal,0.05);
+        assert_shares(&root,&[0.1,0.85,0.05]);
+    }
+
+    #[test]
+    fn resize_uses_the_innermost_split_on_the_axis(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        root.split("a","b",SplitDirection::Horizontal);
+        root.split("b","c",SplitDirection::Vertical);
+        assert!(root.resize_towards("b",FocusDirection::Down));
+        let inner = match &root{
+            PaneNode::Split{children,..}=>children[1].1.clone(),
+            _=>unreachable!()
+        };
+        assert_shares(&inner,&[0.55,0.45]);
+        assert_shares(&root,&[0.5,0.5]);
+        assert!(root.resize_towards("c",FocusDirection::Left));
+        assert_shares(&root,&[0.55,0.45]);
+    }
+
+    #[test]
+    fn neighbour_follows_the_layout(){
+        let mut root = PaneNode::Leaf("a".to_string());
+        root.split("a","b",SplitDirection::Horizontal);
+        root.split("b","c",SplitDirection::Vertical);
+        assert_eq!(root.neighbour("a",FocusDirection::Right),Some("b".to_string()));
+        assert_eq!(root.neighbour("c",FocusDirection::Up),Some("b".to_string()));
+        assert_eq!(root.neighbour("c",FocusDirection::Left),Some("a".to_string()));
+        assert_eq!(root.neighbour("a",FocusDirection::Left),None);
+        assert_eq!(root.neighbour("b",FocusDirection::Down),Some("c".to_string()));
+    }
+}
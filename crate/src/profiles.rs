@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use serde::{Serialize,Deserialize};
+use yew::format::Json;
+use yew::services::storage::{Area,StorageService};
+
+const PROFILES_KEY:&str = "dcode.profiles";
+const TOKEN_KEY_PREFIX:&str = "dcode.token.";
+const RECENT_LIMIT:usize = 8;
+/// Lines of history kept above the screen of each terminal.
+pub const DEFAULT_SCROLLBACK:usize = 1000;
+/// Search walks the whole history, so keep it bounded.
+pub const MAX_SCROLLBACK:usize = 100_000;
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+pub struct ConnectionProfile{
+    pub name:String,
+    pub url:String,
+    /// localStorage key of the access token, set only when the user chose to
+    /// remember it. The token is kept there in plain text, readable by any
+    /// script on this origin; the separate key only keeps it out of the
+    /// profile list.
+    pub token_ref:Option<String>,
+    pub default_project:Option<String>
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,Default,PartialEq)]
+pub struct ProfileStore{
+    pub profiles:Vec<ConnectionProfile>,
+    /// Most recently connected URLs, newest first.
+    pub recent:Vec<String>,
+    pub last_profile:Option<String>,
+    pub auto_connect:bool,
+    /// Last opened project keyed by connection URL.
+    #[serde(default)]
+    pub last_projects:HashMap<String,String>,
+    /// Terminal history length, `DEFAULT_SCROLLBACK` when unset.
+    #[serde(default)]
+    pub scrollback:Option<usize>
+}
+
+fn storage()->Option<StorageService>{
+    match StorageService::new(Area::Local){
+        Ok(storage)=>Some(storage),
+        Err(err)=>{
+            log::error!("Local storage unavailable {}",err);
+            None
+        }
+    }
+}
+
+impl ProfileStore{
+    pub fn load()->ProfileStore{
+        match storage(){
+            Some(storage)=>{
+                let Json(store) = storage.restore(PROFILES_KEY);
+                store.unwrap_or_default()
+            }
+            None=>ProfileStore::default()
+        }
+    }
+
+    pub fn save(&self){
+        if let Some(mut storage)=storage(){
+            storage.store(PROFILES_KEY,Json(self));
+        }
+    }
+
+    pub fn profile(&self,name:&str)->Option<&ConnectionProfile>{
+        self.profiles.iter().find(|profile|profile.name==name)
+    }
+
+    pub fn profile_for_url(&self,url:&str)->Option<&ConnectionProfile>{
+        self.last_profile.as_ref()
+            .and_then(|name|self.profile(name))
+            .filter(|profile|profile.url==url)
+            .or_else(||self.profiles.iter().find(|profile|profile.url==url))
+    }
+
+    /// Adds or replaces the profile with the same name. Without a token any
+    /// token stored for it is removed.
+    pub fn upsert(&mut self,mut profile:ConnectionProfile,token:Option<String>){
+        let token_ref = format!("{}{}",TOKEN_KEY_PREFIX,profile.name);
+        if let Some(mut storage)=storage(){
+            match token{
+                Some(token)=>{
+                    storage.store(&token_ref,Json(&token));
+                    profile.token_ref=Some(token_ref);
+                }
+                None=>{
+                    storage.remove(&token_ref);
+                    profile.token_ref=None;
+                }
+            }
+        }
+        match self.profiles.iter_mut().find(|p|p.name==profile.name){
+            Some(existing)=>*existing=profile,
+            None=>self.profiles.push(profile)
+        }
+    }
+
+    pub fn remove(&mut self,name:&str){
+        if let Some(idx)=self.profiles.iter().position(|profile|profile.name==name){
+            let profile = self.profiles.remove(idx);
+            if let (Some(token_ref),Some(mut storage))=(profile.token_ref,storage()){
+                storage.remove(&token_ref);
+            }
+        }
+        if self.last_profile.as_ref().map(|last|last==name).unwrap_or(false){
+            self.last_profile=None;
+        }
+    }
+
+    pub fn push_recent(&mut self,url:&str){
+        self.recent.retain(|recent|recent!=url);
+        self.recent.insert(0,url.to_string());
+        self.recent.truncate(RECENT_LIMIT);
+    }
+
+    pub fn last_project(&self,url:&str)->Option<String>{
+        self.last_projects.get(url).cloned()
+    }
+
+    pub fn set_last_project(&mut self,url:&str,project:&str){
+        self.last_projects.insert(url.to_string(),project.to_string());
+    }
+
+    pub fn scrollback(&self)->usize{
+        self.scrollback.unwrap_or(DEFAULT_SCROLLBACK)
+    }
+
+    pub fn set_scrollback(&mut self,lines:usize){
+        self.scrollback=Some(lines.min(MAX_SCROLLBACK));
+    }
+
+    pub fn token(profile:&ConnectionProfile)->Option<String>{
+        let token_ref = profile.token_ref.as_ref()?;
+        let Json(token) = storage()?.restore(token_ref);
+        token.ok()
+    }
+}
@@ -0,0 +1,163 @@
+use yew::prelude::*;
+
+use super::socket_agent::{AgentInput,AgentOutput,Request,RequestError,Response,SocketAgent};
+
+pub const PROJECTS_DIR:&str = "/projects";
+
+pub struct ProjectPicker{
+    props:Props,
+    link:ComponentLink<Self>,
+    agent_bridge:Box<dyn yew::Bridge<SocketAgent>>,
+    projects:Vec<String>,
+    is_loading:bool,
+    error:Option<String>
+}
+
+#[derive(Clone,Properties)]
+pub struct Props{
+    pub last_project:Option<String>,
+    pub onopen:Callback<String>,
+    pub onnew:Callback<()>
+}
+
+pub enum Msg{
+    Refresh,
+    Reply(Result<Response,RequestError>),
+    Open(String),
+    New,
+    Ignore
+}
+
+impl Component for ProjectPicker{
+
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props:Props, link:ComponentLink<Self>) -> Self {
+        let agent_bridge = SocketAgent::bridge(link.callback(|data|match data{
+            AgentOutput::Reply(_,response)=>Msg::Reply(response),
+            _=>Msg::Ignore
+        }));
+        ProjectPicker{
+            props,
+            link,
+            agent_bridge,
+            projects:vec![],
+            is_loading:false,
+            error:None
+        }
+    }
+
+    fn mounted(&mut self) -> ShouldRender {
+        self.update(Msg::Refresh)
+    }
+
+    fn change(&mut self, props:Props) -> ShouldRender {
+        self.props=props;
+        true
+    }
+
+    fn update(&mut self, msg: Msg) -> ShouldRender {
+        match msg{
+            Msg::Refresh=>{
+                self.agent_bridge.send(AgentInput::Call(Request::FsList(PROJECTS_DIR.to_string())));
+                self.is_loading=true;
+                true
+            }
+            Msg::Reply(response)=>{
+                self.is_loading=false;
+                match response{
+                    Ok(Response::FsList(entries))=>{
+                        let mut projects:Vec<String> = entries.into_iter()
+                            .filter(|entry|entry.is_dir && !entry.name.starts_with('.'))
+                            .map(|entry|entry.name)
+                            .collect();
+                        projects.sort();
+                        self.projects=projects;
+                        self.error=None;
+                    }
+                    Ok(_)=>{}
+                    Err(err)=>{
+                        self.error=Some(format!("Cannot list projects ({:?})",err));
+                    }
+                }
+                true
+            }
+            Msg::Open(name)=>{
+                self.props.onopen.emit(name);
+                false
+            }
+            Msg::New=>{
+                self.props.onnew.emit(());
+                false
+            }
+            Msg::Ignore=>false
+        }
+    }
+
+    fn view(&self) -> Html {
+        html!{
+            <nav class="panel">
+                <p class="panel-heading">{"Projects"}</p>
+                <div class="panel-block">
+                    <div class="buttons">
+                        <button class="button is-success is-small" onclick=self.link.callback(|_|Msg::New)>
+                            <span class="icon is-small">
+                                <i class="fas fa-plus"></i>
+                            </span>
+                            <span>{"New project"}</span>
+                        </button>
+                        <button class={
+                            if self.is_loading{
+                                "button is-small is-loading"
+                            }else{
+                                "button is-small"
+                            }
+                        } onclick=self.link.callback(|_|Msg::Refresh)>
+                            <span class="icon is-small">
+                                <i class="fas fa-sync"></i>
+                            </span>
+                        </button>
+                    </div>
+                </div>
+                {
+                    match &self.error{
+                        Some(err)=>html!{
+                            <div class="panel-block">
+                                <p class="help is-danger">{err}</p>
+                            </div>
+                        },
+                        None=>html!{}
+                    }
+                }
+                { for self.projects.iter().map(|project| {
+                    let name = project.clone();
+                    let is_last = self.props.last_project.as_ref()==Some(project);
+                    html!{
+                        <a class={
+                            if is_last{
+                                "panel-block is-active"
+                            }else{
+                                "panel-block"
+                            }
+                        } key=project.clone() onclick=self.link.callback(move |_|Msg::Open(name.clone()))>
+                            <span class="panel-icon">
+                                <i class="fas fa-folder" aria-hidden="true"></i>
+                            </span>
+                            {project}
+                            {
+                                if is_last{
+                                    html!{
+                                        <span class="tag is-info is-light" style="margin-left:0.5em;">{"Last opened"}</span>
+                                    }
+                                }else{
+                                    html!{}
+                                }
+                            }
+                        </a>
+                    }
+                })}
+            </nav>
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use yew::Callback;
+use yew::services::timeout::{TimeoutService,TimeoutTask};
+
+use super::terminal_src::VtParser;
+
+/// History kept when replaying a recording into plain text.
+const REPLAY_SCROLLBACK:usize = 100_000;
+/// How long a download's object URL is kept; the browser fetches it after
+/// the click returns.
+const DOWNLOAD_URL_LIFETIME:Duration = Duration::from_secs(60);
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum RecordedEvent{
+    /// Raw "exec" output.
+    Output(String),
+    /// The terminal was resized to rows and columns.
+    Resize(u16,u16)
+}
+
+/// Raw "exec" output and resizes of a terminal with the time they happened.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Recording{
+    /// Milliseconds since the epoch when recording started.
+    pub started:f64,
+    /// Size when recording started.
+    pub rows:u16,
+    pub cols:u16,
+    /// Seconds since `started` and what happened then.
+    pub events:Vec<(f64,RecordedEvent)>
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ExportFormat{
+    /// What was on screen, escape sequences interpreted and dropped.
+    PlainText,
+    /// The raw stream, colors and all, for `cat` in another terminal.
+    Ansi,
+    /// asciicast v2, playable by asciinema.
+    Asciicast
+}
+
+impl ExportFormat{
+    pub fn extension(self)->&'static str{
+        match self{
+            ExportFormat::PlainText=>"txt",
+            ExportFormat::Ansi=>"ansi",
+            ExportFormat::Asciicast=>"cast"
+        }
+    }
+
+    pub fn mime(self)->&'static str{
+        match self{
+            ExportFormat::PlainText|ExportFormat::Ansi=>"text/plain",
+            ExportFormat::Asciicast=>"application/x-asciicast"
+        }
+    }
+}
+
+impl Recording{
+    pub fn start(rows:u16,cols:u16)->Recording{
+        Recording{
+            started:js_sys::Date::now(),
+            rows,
+            cols,
+            events:vec![]
+        }
+    }
+
+    fn elapsed(&self)->f64{
+        (js_sys::Date::now()-self.started)/1000.0
+    }
+
+    pub fn push(&mut self,output:&str){
+        let time = self.elapsed();
+        self.events.push((time,RecordedEvent::Output(output.to_string())));
+    }
+
+    pub fn resize(&mut self,rows:u16,cols:u16){
+        let time = self.elapsed();
+        self.events.push((time,RecordedEvent::Resize(rows,cols)));
+    }
+
+    pub fn export(&self,format:ExportFormat)->String{
+        match format{
+            ExportFormat::PlainText=>self.to_plain_text(),
+            ExportFormat::Ansi=>self.events.iter()
+                .filter_map(|(_,event)|match event{
+                    RecordedEvent::Output(output)=>Some(output.as_str()),
+                    RecordedEvent::Resize(..)=>None
+                })
+                .collect(),
+            ExportFormat::Asciicast=>self.to_asciicast()
+        }
+    }
+
+    /// Replays the stream and keeps the resulting scrollback and screen.
+    fn to_plain_text(&self)->String{
+        let mut parser = VtParser::new(self.rows,self.cols,REPLAY_SCROLLBACK,0.0);
+        for (_,event) in &self.events{
+            match event{
+                RecordedEvent::Output(output)=>parser.parse(output),
+                RecordedEvent::Resize(rows,cols)=>parser.set_size(*rows,*cols)
+            }
+        }
+        let mut lines:Vec<String> = parser.lines().iter().map(|line|line.trim_end().to_string()).collect();
+        while lines.last().map(|line|line.is_empty()).unwrap_or(false){
+            lines.pop();
+        }
+        lines.join("\n")+"\n"
+    }
+
+    fn to_asciicast(&self)->String{
+        let header = serde_json::json!({
+            "version":2,
+            "width":self.cols,
+            "height":self.rows,
+            "timestamp":(self.started/1000.0).floor() as u64
+        });
+        let mut out = header.to_string()+"\n";
+        for (time,event) in &self.events{
+            let line = match event{
+                RecordedEvent::Output(output)=>serde_json::json!([time,"o",output]),
+                RecordedEvent::Resize(rows,cols)=>serde_json::json!([time,"r",format!("{}x{}",cols,rows)])
+            };
+            out+=&line.to_string();
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Saves `content` through the browser's download prompt. The object URL is
+/// revoked, and `done` emitted, once the returned task fires; dropping the
+/// task early keeps the URL alive until the page unloads.
+pub fn download(filename:&str,content:&str,mime:&str,done:Callback<()>)->Result<TimeoutTask,JsValue>{
+    let parts = js_sys::Array::of1(&JsValue::from_str(content));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_(mime);
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts,&options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let document = yew::utils::document();
+    let anchor = document.create_element("a")?.dyn_into::<web_sys::HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    let _ = anchor.set_attribute("style","display:none;");
+    // Firefox ignores clicks on anchors outside the document.
+    let body = document.body().ok_or_else(||JsValue::from_str("Document has no body"))?;
+    body.append_child(&anchor)?;
+    anchor.click();
+    anchor.remove();
+    Ok(TimeoutService::new().spawn(DOWNLOAD_URL_LIFETIME,Callback::from(move |_|{
+        let _ = web_sys::Url::revoke_object_url(&url);
+        done.emit(());
+    })))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn recording()->Recording{
+        Recording{
+            started:1_600_000_000_500.0,
+            rows:2,
+            cols:10,
+            events:vec![
+                (0.5,RecordedEvent::Output("\x1b[1mone\x1b[0m\r\ntwo\r\n".to_string())),
+                (1.0,RecordedEvent::Resize(3,20)),
+                (1.5,RecordedEvent::Output("three\r\nfour   ".to_string()))
+            ]
+        }
+    }
+
+    #[test]
+    fn plain_text_keeps_history_and_drops_escapes(){
+        assert_eq!(recording().export(ExportFormat::PlainText),"one\ntwo\nthree\nfour\n");
+    }
+
+    #[test]
+    fn ansi_is_the_raw_output(){
+        assert_eq!(
+            recording().export(ExportFormat::Ansi),
+            "\x1b[1mone\x1b[0m\r\ntwo\r\nthree\r\nfour   "
+        );
+    }
+
+    #[test]
+    fn asciicast_has_output_and_resize_events(){
+        let cast = recording().export(ExportFormat::Asciicast);
+        let lines:Vec<serde_json::Value> = cast.lines().map(|line|serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines,vec![
+            serde_json::json!({"version":2,"width":10,"height":2,"timestamp":1_600_000_000u64}),
+            serde_json::json!([0.5,"o","\x1b[1mone\x1b[0m\r\ntwo\r\n"]),
+            serde_json::json!([1.0,"r","20x3"]),
+            serde_json::json!([1.5,"o","three\r\nfour   "])
+        ]);
+    }
+}
@@ -1,5 +1,7 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::{HashMap,VecDeque};
+use std::time::Duration;
 use web_sys::MessageEvent;
 use web_sys::WebSocket;
 use yew::agent::HandlerId;
@@ -7,49 +9,496 @@ use yew::agent::Context;
 use yew::agent::Agent;
 use yew::worker::AgentLink;
 use yew::prelude::*;
+use yew::services::timeout::{TimeoutService,TimeoutTask};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
+use std::convert::TryFrom;
 use super::file_manager::FsEntry;
+use super::connect_form::WebSocketMeta;
 
 use serde::{Serialize,Deserialize};
 
+/// Wire envelope spoken by the backend. Components should build a `Request`
+/// and match on `Response` instead of filling these fields by hand.
 #[derive(Serialize,Deserialize,Debug,Clone)]
 pub struct TransferData{
     pub command:String,
     pub value:String,
-    pub args:Vec<String>
+    pub args:Vec<String>,
+    /// Set on requests made through `AgentInput::Call`; the backend echoes it
+    /// back on the matching reply.
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id:Option<u64>
+}
+
+const REQUEST_TIMEOUT:Duration = Duration::from_secs(10);
+const RECONNECT_BASE_DELAY_MS:u64 = 500;
+const RECONNECT_MAX_DELAY_MS:u64 = 30_000;
+const OUTBOUND_QUEUE_LIMIT:usize = 256;
+
+/// Which message gives way when the outbound queue is full.
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq)]
+pub enum OverflowPolicy{
+    /// Evict the oldest queued message, failing its call with `Dropped`.
+    DropOldest,
+    /// Keep the queue as is and fail the message being sent.
+    DropNewest
+}
+
+impl OverflowPolicy{
+    pub const ALL:[OverflowPolicy;2] = [OverflowPolicy::DropOldest,OverflowPolicy::DropNewest];
+
+    pub fn name(self)->&'static str{
+        match self{
+            OverflowPolicy::DropOldest=>"drop-oldest",
+            OverflowPolicy::DropNewest=>"drop-newest"
+        }
+    }
+
+    pub fn from_name(name:&str)->Option<OverflowPolicy>{
+        OverflowPolicy::ALL.iter().cloned().find(|policy|policy.name()==name)
+    }
+}
+
+impl Default for OverflowPolicy{
+    fn default()->Self{
+        OverflowPolicy::DropOldest
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(tag="kind",content="data")]
+pub enum Request{
+    Auth(String),
+    /// Offers frame encodings for server to client traffic, preferred first.
+    Encoding(Vec<String>),
+    Ping(f64),
+    ProcessNew(String),
+    ProcessList,
+    /// Asks the backend to resend the current screen of a running process as
+    /// "exec" output, used when reattaching after a reload.
+    ProcessReplay(String),
+    ProcessResize{termid:String,rows:u16,cols:u16},
+    ProcessKill(String),
+    Exec{termid:String,input:String},
+    FsList(String),
+    FsOpen(String),
+    FsNew(FsEntry),
+    FsNewDir(String),
+    FsSave{file:FsEntry,content:String}
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(tag="kind",content="data")]
+pub enum Response{
+    AuthOk,
+    AuthFailed(String),
+    Encoding(String),
+    Pong(f64),
+    ProcessList(Vec<String>),
+    Exec{termid:String,output:String},
+    FsList(Vec<FsEntry>),
+    FsOpen{path:String,content:String},
+    FsSave(FsEntry)
+}
+
+impl From<Request> for TransferData{
+    fn from(request:Request)->Self{
+        let (command,value,args)=match request{
+            Request::Auth(token)=>("auth","token".to_string(),vec![token]),
+            Request::Encoding(encodings)=>("encoding","negotiate".to_string(),encodings),
+            Request::Ping(time)=>("ping","0".to_string(),vec![format!("{}",time)]),
+            Request::ProcessNew(termid)=>("process","new".to_string(),vec![termid]),
+            Request::ProcessList=>("process","list".to_string(),vec![]),
+            Request::ProcessReplay(termid)=>("process","replay".to_string(),vec![termid]),
+            Request::ProcessKill(termid)=>("process","kill".to_string(),vec![termid]),
+            Request::ProcessResize{termid,rows,cols}=>{
+                ("process","resize".to_string(),vec![termid,rows.to_string(),cols.to_string()])
+            }
+            Request::Exec{termid,input}=>("exec",termid,vec![input]),
+            Request::FsList(path)=>("fs","list".to_string(),vec![path]),
+            Request::FsOpen(path)=>("fs","open".to_string(),vec![path]),
+            Request::FsNew(file)=>{
+                let ffs = serde_json::to_string(&file).expect("Cant convert to string");
+                ("fs","new".to_string(),vec![ffs])
+            }
+            Request::FsNewDir(path)=>("fs","new_dir".to_string(),vec![path]),
+            Request::FsSave{file,content}=>{
+                let ffs = serde_json::to_string(&file).expect("Cant convert to string");
+                ("fs","save".to_string(),vec![ffs,content])
+            }
+        };
+        TransferData{
+            command:command.to_string(),
+            value,
+            args,
+            id:None
+        }
+    }
+}
+
+impl TryFrom<TransferData> for Response{
+    type Error = TransferData;
+
+    /// Decodes the positional `args` of a backend message, handing the
+    /// envelope back untouched when it is not understood.
+    fn try_from(data:TransferData)->Result<Self,Self::Error>{
+        let response = match (data.command.as_str(),data.value.as_str()){
+            ("auth","ok")=>Some(Response::AuthOk),
+            ("auth","failed")=>Some(Response::AuthFailed(data.args.first().cloned().unwrap_or_default())),
+            ("encoding",encoding)=>Some(Response::Encoding(encoding.to_string())),
+            ("ping",_)=>data.args.get(1).and_then(|time|time.parse().ok()).map(Response::Pong),
+            ("process","list")=>Some(Response::ProcessList(data.args.clone())),
+            ("exec",termid)=>data.args.get(1).map(|output|Response::Exec{
+                termid:termid.to_string(),
+                output:output.clone()
+            }),
+            ("fs","list")=>Some(Response::FsList(
+                data.args.iter().filter_map(|entry|serde_json::from_str(entry).ok()).collect()
+            )),
+            ("fs","open")=>match (data.args.first(),data.args.get(1)){
+                (Some(path),Some(content))=>Some(Response::FsOpen{
+                    path:path.clone(),
+                    content:content.clone()
+                }),
+                _=>None
+            },
+            ("fs","save")=>data.args.first().and_then(|file|serde_json::from_str(file).ok()).map(Response::FsSave),
+            _=>None
+        };
+        response.ok_or(data)
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+pub enum RequestError{
+    NotConnected,
+    Dropped,
+    Timeout,
+    Malformed
 }
 
 #[derive(Serialize,Deserialize)]
 pub enum AgentInput{
-    ConnectSocket(String),
-    SendData(TransferData),
+    /// Opens the socket, authenticating with the token when one is given.
+    /// Messages sent before it opens are queued, the policy deciding what
+    /// happens once the queue is full.
+    ConnectSocket(WebSocketMeta,Option<String>,OverflowPolicy),
+    Send(Request),
+    /// Like `Send`, but the reply is delivered only to the calling bridge as
+    /// `AgentOutput::Reply` instead of being broadcast.
+    Call(Request),
     SaveFile(FsEntry)
 }
 
 #[derive(Serialize,Deserialize)]
 pub enum AgentOutput{
-    Connected(String),
+    Connected(WebSocketMeta),
     ErrorConnecting,
+    AuthFailed(String),
     Disconnected,
-    SocketMessage(TransferData),
+    /// The socket was lost and reconnect attempt `n` is scheduled.
+    Reconnecting(u32),
+    Response(Response),
+    Reply(Request,Result<Response,RequestError>),
     SaveFile(FsEntry)
 }
 
+/// Encoding of binary frames from the backend. Text frames are always JSON
+/// and everything we send stays JSON text.
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum FrameEncoding{
+    Json,
+    MsgPack
+}
+
+impl FrameEncoding{
+    const OFFERED:[FrameEncoding;2] = [FrameEncoding::MsgPack,FrameEncoding::Json];
+
+    fn name(self)->&'static str{
+        match self{
+            FrameEncoding::Json=>"json",
+            FrameEncoding::MsgPack=>"msgpack"
+        }
+    }
+
+    fn from_name(name:&str)->Option<FrameEncoding>{
+        FrameEncoding::OFFERED.iter().cloned().find(|encoding|encoding.name()==name)
+    }
+
+    /// Decodes a frame received while this encoding is negotiated.
+    fn decode(self,frame:Frame)->Result<TransferData,String>{
+        match frame{
+            Frame::Text(text)=>serde_json::from_str(&text).map_err(|err|err.to_string()),
+            Frame::Binary(bytes)=>match self{
+                FrameEncoding::MsgPack=>rmp_serde::from_read_ref(&bytes).map_err(|err|err.to_string()),
+                FrameEncoding::Json=>serde_json::from_slice(&bytes).map_err(|err|err.to_string())
+            }
+        }
+    }
+}
+
+pub enum Frame{
+    Text(String),
+    Binary(Vec<u8>)
+}
+
+struct PendingRequest{
+    handler:HandlerId,
+    request:Request,
+    _timeout:TimeoutTask
+}
+
 pub struct SocketAgent{
     link: AgentLink<Self>,
     subscribers: Vec<HandlerId>,
     socket:Option<WebSocket>,
-    updatecallback:Callback<(WebSocket,String)>,
-    socketmessagecallback:Callback<TransferData>
+    meta:Option<WebSocketMeta>,
+    token:Option<String>,
+    /// Socket that is open but still waiting for the backend to accept `token`.
+    authenticating:Option<WebSocket>,
+    auth_task:Option<TimeoutTask>,
+    encoding:FrameEncoding,
+    has_connected:bool,
+    reconnect_attempt:u32,
+    reconnect_task:Option<TimeoutTask>,
+    outbox:VecDeque<TransferData>,
+    overflow:OverflowPolicy,
+    /// Bumped for every socket opened; events tagged with an older one come
+    /// from a socket that was replaced and are ignored.
+    generation:u64,
+    next_request_id:u64,
+    pending:HashMap<u64,PendingRequest>
 }
 
+/// Socket events carry the `generation` of the socket they came from.
 pub enum Msg{
-    Connected((WebSocket,String)),
-    SocketMessage(TransferData)
+    Opened(u64,WebSocket),
+    Frame(u64,Frame),
+    Error(u64),
+    Closed(u64),
+    Reconnect,
+    AuthTimeout,
+    RequestTimeout(u64)
+}
+
+impl SocketAgent{
+    fn respond_all(&self,output:impl Fn()->AgentOutput){
+        for subs in self.subscribers.iter(){
+            self.link.respond(*subs, output())
+        }
+    }
+
+    /// Closes the live or authenticating socket, if any, without reporting it.
+    fn close_socket(&mut self){
+        self.generation+=1;
+        self.auth_task=None;
+        for socket in self.socket.take().into_iter().chain(self.authenticating.take()){
+            let _ = socket.close();
+        }
+    }
+
+    fn open_socket(&mut self){
+        let url = match &self.meta{
+            Some(meta)=>meta.url.clone(),
+            None=>return
+        };
+        self.generation+=1;
+        let generation = self.generation;
+        match WebSocket::new(&url){
+            Ok(ws)=>{
+                let wss= ws.clone();
+                let opencallback = self.link.callback(move |ws|Msg::Opened(generation,ws));
+                let onopen_callback = Closure::wrap(Box::new(move |_| {
+                    opencallback.emit(wss.clone());
+                }) as Box<dyn FnMut(JsValue)>);
+                ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+                onopen_callback.forget();
+
+                ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
+                let msgcallback = self.link.callback(move |frame|Msg::Frame(generation,frame));
+                let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
+                    // handle message
+                    let data = e.data();
+                    if let Some(text)=data.as_string(){
+                        msgcallback.emit(Frame::Text(text));
+                    }else if data.is_instance_of::<js_sys::ArrayBuffer>(){
+                        msgcallback.emit(Frame::Binary(js_sys::Uint8Array::new(&data).to_vec()));
+                    }else{
+                        log::error!("Unsupported frame {:?}",data);
+                    }
+                }) as Box<dyn FnMut(MessageEvent)>);
+                ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+                onmessage_callback.forget();
+
+                let errorcallback = self.link.callback(move |_|Msg::Error(generation));
+                let onerror_callback = Closure::wrap(Box::new(move |_| {
+                    errorcallback.emit(());
+                }) as Box<dyn FnMut(JsValue)>);
+                ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+                onerror_callback.forget();
+
+                let closecallback = self.link.callback(move |_|Msg::Closed(generation));
+                let onclose_callback = Closure::wrap(Box::new(move |_| {
+                    closecallback.emit(());
+                }) as Box<dyn FnMut(JsValue)>);
+                ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+                onclose_callback.forget();
+            }
+            Err(e)=>{
+                log::debug!("Cannot connect {:#?}",e);
+                if self.has_connected{
+                    self.schedule_reconnect();
+                }else{
+                    self.respond_all(||AgentOutput::ErrorConnecting);
+                }
+            }
+        }
+    }
+
+    fn handle_message(&mut self,msg:TransferData){
+        if self.authenticating.is_some(){
+            match Response::try_from(msg){
+                Ok(Response::AuthOk)=>{
+                    self.auth_task=None;
+                    if let Some(socket)=self.authenticating.take(){
+                        self.ready(socket);
+                    }
+                }
+                Ok(Response::AuthFailed(reason))=>self.auth_failed(reason),
+                _=>log::debug!("Ignoring message received before authentication")
+            }
+            return;
+        }
+        if let Some(pending)=msg.id.and_then(|id|self.pending.remove(&id)){
+            let response = Response::try_from(msg).map_err(|msg|{
+                log::error!("Malformed reply {:#?}",msg);
+                RequestError::Malformed
+            });
+            self.link.respond(pending.handler, AgentOutput::Reply(pending.request,response));
+            return;
+        }
+        match Response::try_from(msg){
+            Ok(Response::Encoding(name))=>{
+                match FrameEncoding::from_name(&name){
+                    Some(encoding)=>self.encoding=encoding,
+                    None=>log::error!("Backend chose unknown encoding {}",name)
+                }
+            }
+            Ok(response)=>{
+                self.respond_all(||AgentOutput::Response(response.clone()));
+            }
+            Err(msg)=>log::info!("Unknown message {:#?}",msg)
+        }
+    }
+
+    /// Makes `socket` the live connection, announcing it once queued
+    /// messages have gone out.
+    fn ready(&mut self,socket:WebSocket){
+        let offered = FrameEncoding::OFFERED.iter().map(|encoding|encoding.name().to_string()).collect();
+        let _ = write_frame(&socket,&TransferData::from(Request::Encoding(offered)));
+        self.encoding=FrameEncoding::Json;
+        self.socket=Some(socket);
+        self.has_connected=true;
+        self.reconnect_attempt=0;
+        self.flush_outbox();
+        if let Some(meta)=self.meta.clone(){
+            self.respond_all(||AgentOutput::Connected(meta.clone()));
+        }
+    }
+
+    /// Gives up on the connection without reconnecting, since retrying with
+    /// the same credentials would fail again.
+    fn auth_failed(&mut self,reason:String){
+        log::error!("Authentication failed {}",reason);
+        if let Some(socket)=self.authenticating.take(){
+            let _ = socket.close();
+        }
+        self.auth_task=None;
+        self.has_connected=false;
+        self.outbox.clear();
+        for (_,pending) in self.pending.drain(){
+            self.link.respond(pending.handler, AgentOutput::Reply(pending.request,Err(RequestError::NotConnected)));
+        }
+        self.respond_all(||AgentOutput::AuthFailed(reason.clone()));
+    }
+
+    /// Retries with an exponentially growing delay, capped at `RECONNECT_MAX_DELAY_MS`.
+    fn schedule_reconnect(&mut self){
+        let delay = RECONNECT_BASE_DELAY_MS
+            .saturating_mul(1<<self.reconnect_attempt.min(16))
+            .min(RECONNECT_MAX_DELAY_MS);
+        self.reconnect_attempt+=1;
+        let attempt = self.reconnect_attempt;
+        log::info!("Reconnecting in {}ms (attempt {})",delay,attempt);
+        self.respond_all(||AgentOutput::Reconnecting(attempt));
+        self.reconnect_task=Some(TimeoutService::new().spawn(
+            Duration::from_millis(delay),
+            self.link.callback(|_|Msg::Reconnect)
+        ));
+    }
+
+    /// Sends right away when the socket is open, otherwise queues the message
+    /// until the pending (re)connection opens.
+    fn send_data(&mut self,data:TransferData)->Result<(),RequestError>{
+        match &self.socket{
+            Some(socket)=>write_frame(socket,&data),
+            None if self.meta.is_some()=>self.enqueue(data),
+            None=>{
+                log::debug!("Trying to send data without connection {:#?}",data);
+                Err(RequestError::NotConnected)
+            }
+        }
+    }
+
+    fn enqueue(&mut self,data:TransferData)->Result<(),RequestError>{
+        if self.outbox.len()<OUTBOUND_QUEUE_LIMIT{
+            self.outbox.push_back(data);
+            return Ok(());
+        }
+        match self.overflow{
+            OverflowPolicy::DropOldest=>{
+                if let Some(dropped)=self.outbox.pop_front(){
+                    log::warn!("Outbound queue full, dropping {:#?}",dropped);
+                    if let Some(pending)=dropped.id.and_then(|id|self.pending.remove(&id)){
+                        self.link.respond(pending.handler, AgentOutput::Reply(pending.request,Err(RequestError::Dropped)));
+                    }
+                }
+                self.outbox.push_back(data);
+                Ok(())
+            }
+            OverflowPolicy::DropNewest=>{
+                log::warn!("Outbound queue full, dropping {:#?}",data);
+                Err(RequestError::Dropped)
+            }
+        }
+    }
+
+    fn flush_outbox(&mut self){
+        let queued:Vec<TransferData> = self.outbox.drain(..).collect();
+        for data in queued{
+            let _ = self.send_data(data);
+        }
+    }
 }
 
 
+fn write_frame(socket:&WebSocket,data:&TransferData)->Result<(),RequestError>{
+    match serde_json::to_string(data){
+        Ok(data)=>{
+            if let Err(err)=socket.send_with_str(&data){
+                log::error!("{:?}",err);
+                return Err(RequestError::NotConnected);
+            }
+            Ok(())
+        }
+        Err(err)=>{
+            log::error!("{:?}",err);
+            Err(RequestError::Malformed)
+        }
+    }
+}
+
 impl Agent for SocketAgent{
 
     type Reach = Context;
@@ -60,11 +509,22 @@ impl Agent for SocketAgent{
     fn create(link: AgentLink<Self>)->Self{
 
         SocketAgent{
-            updatecallback:link.callback(|sock|Msg::Connected(sock)),
-            socketmessagecallback:link.callback(|msg|Msg::SocketMessage(msg)),
             link,
             socket:None,
+            meta:None,
+            token:None,
+            authenticating:None,
+            auth_task:None,
+            encoding:FrameEncoding::Json,
+            has_connected:false,
+            reconnect_attempt:0,
+            reconnect_task:None,
+            outbox:VecDeque::new(),
+            overflow:OverflowPolicy::default(),
+            generation:0,
             subscribers:vec![],
+            next_request_id:0,
+            pending:HashMap::new()
         }
     }
 
@@ -76,107 +536,241 @@ impl Agent for SocketAgent{
         if let Some(idx)=self.subscribers.iter().position(|id|id==&_id){
             self.subscribers.remove(idx);
         }
+        self.pending.retain(|_,pending|pending.handler!=_id);
     }
 
     fn update(&mut self,msg: Self::Message){
         match msg{
-            Msg::Connected(socket)=>{
-
-                let subscribers = self.subscribers.clone();
-                let linkclone = self.link.clone();
-                let msgcallback = self.socketmessagecallback.clone();
-                let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
-                    // handle message
-                    let response = e
-                        .data()
-                        .as_string()
-                        .expect("Can't convert received data to a string");
-                    let tdata = serde_json::from_str::<TransferData>(&response);
-                    match tdata{
-                        Ok(tdata)=>{
-                            msgcallback.emit(tdata);
-                        }
-                        Err(err)=>{
-                            log::error!("{}",err);
+            Msg::Opened(generation,socket) if generation!=self.generation=>{
+                let _ = socket.close();
+            }
+            Msg::Frame(generation,_)|Msg::Error(generation)|Msg::Closed(generation) if generation!=self.generation=>{
+                log::debug!("Ignoring event from a replaced socket");
+            }
+            Msg::Opened(_,socket)=>{
+                match self.token.clone(){
+                    Some(token)=>{
+                        if write_frame(&socket,&TransferData::from(Request::Auth(token))).is_err(){
+                            let _ = socket.close();
+                            return;
                         }
+                        self.authenticating=Some(socket);
+                        self.auth_task=Some(TimeoutService::new().spawn(
+                            REQUEST_TIMEOUT,
+                            self.link.callback(|_|Msg::AuthTimeout)
+                        ));
                     }
-                }) as Box<dyn FnMut(MessageEvent)>);
-                socket.0.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-
-                onmessage_callback.forget();
-                self.socket=Some(socket.0);
-                for subs in self.subscribers.iter(){
-                    self.link.respond(subs.clone(), AgentOutput::Connected(socket.1.clone()))
+                    None=>self.ready(socket)
                 }
-
             },
-            Msg::SocketMessage(msg)=>{
-                for subs in self.subscribers.iter(){
-                    self.link.respond(subs.clone(), AgentOutput::SocketMessage(msg.clone()))
+            Msg::Frame(_,frame)=>{
+                match self.encoding.decode(frame){
+                    Ok(msg)=>self.handle_message(msg),
+                    Err(err)=>log::error!("{}",err)
+                }
+            }
+            Msg::Error(_)=>{
+                // A lost connection is followed by `Closed`, which drives reconnection.
+                if !self.has_connected{
+                    self.respond_all(||AgentOutput::ErrorConnecting);
+                }
+            }
+            Msg::Closed(_)=>{
+                let was_open = self.socket.take().is_some();
+                if self.authenticating.take().is_some(){
+                    self.auth_task=None;
+                    if !self.has_connected{
+                        self.auth_failed("Connection closed during authentication".to_string());
+                        return;
+                    }
+                }
+                if !self.has_connected{
+                    return;
+                }
+                // Requests already on the wire will never be answered; queued ones still may.
+                let outbox = &self.outbox;
+                let lost:Vec<u64> = self.pending.keys()
+                    .filter(|id|!outbox.iter().any(|data|data.id==Some(**id)))
+                    .cloned()
+                    .collect();
+                for id in lost{
+                    if let Some(pending)=self.pending.remove(&id){
+                        self.link.respond(pending.handler, AgentOutput::Reply(pending.request,Err(RequestError::NotConnected)));
+                    }
+                }
+                if was_open{
+                    self.respond_all(||AgentOutput::Disconnected);
+                }
+                self.schedule_reconnect();
+            }
+            Msg::Reconnect=>{
+                self.reconnect_task=None;
+                self.open_socket();
+            }
+            Msg::AuthTimeout=>{
+                // A slow backend is not a rejection: drop the socket and let
+                // `Closed` retry as for any other lost connection.
+                if let Some(socket)=self.authenticating.take(){
+                    log::warn!("Timed out waiting for the backend to authenticate");
+                    self.auth_task=None;
+                    let _ = socket.close();
+                    if !self.has_connected{
+                        self.respond_all(||AgentOutput::ErrorConnecting);
+                    }
+                }
+            }
+            Msg::RequestTimeout(id)=>{
+                self.outbox.retain(|data|data.id!=Some(id));
+                if let Some(pending)=self.pending.remove(&id){
+                    self.link.respond(pending.handler, AgentOutput::Reply(pending.request,Err(RequestError::Timeout)));
                 }
             }
         }
     }
 
-    fn handle_input(&mut self,msg: Self::Input, _id: HandlerId){
+    fn handle_input(&mut self,msg: Self::Input, id: HandlerId){
         match msg{
-            Self::Input::ConnectSocket(url)=>{
-                let subscribers = self.subscribers.clone();
-                let linkclone = self.link.clone();
-
-                let onerror_callback = Closure::wrap(Box::new(move |_| {
-                    for subs in subscribers.clone(){
-                        linkclone.clone().respond(subs, AgentOutput::ErrorConnecting)
-                    }
-                }) as Box<dyn FnMut(JsValue)>);
-
-
-                let ws = WebSocket::new(&format!("ws://{}/ws",url));
-                match ws{
-                    Ok(ws)=>{
-                        let wss= ws.clone();
-                        let updatecallback = self.updatecallback.clone();
-                        let onopen_callback = Closure::wrap(Box::new(move |_| {
-                            updatecallback.emit((wss.clone(),url.clone()));
-                        }) as Box<dyn FnMut(JsValue)>);
-
-                        ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-                        onopen_callback.forget();
-
-                        ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-                        onerror_callback.forget();
-                    }
-                    Err(e)=>{
-                        log::debug!("Cannot connect {:#?}",e);
-                        for subs in &self.subscribers{
-                            self.link.respond(subs.clone(), AgentOutput::ErrorConnecting)
-                        }
-                    }
+            Self::Input::ConnectSocket(meta,token,overflow)=>{
+                if self.meta.as_ref()!=Some(&meta){
+                    self.outbox.clear();
                 }
+                self.meta=Some(meta);
+                self.token=token;
+                self.overflow=overflow;
+                self.has_connected=false;
+                self.reconnect_attempt=0;
+                self.reconnect_task=None;
+                self.close_socket();
+                self.open_socket();
             }
-            Self::Input::SendData(data)=>{
-                match &mut self.socket{
-                    Some(socket)=>{
-
-                        match serde_json::to_string(&data){
-                            Ok(data)=>{
-                                if let Err(err)=socket.send_with_str(&data){
-                                    log::error!("{:?}",err);
-                                }
-                            }
-                            Err(err)=>{
-                                log::error!("{:?}",err);
-                            }
-                        }
-                    }
-                    None=>log::debug!("Trying to send data without connection {:#?}",data)
+            Self::Input::Send(request)=>{
+                let _ = self.send_data(TransferData::from(request));
+            }
+            Self::Input::Call(request)=>{
+                let request_id = self.next_request_id;
+                self.next_request_id+=1;
+
+                let mut data = TransferData::from(request.clone());
+                data.id=Some(request_id);
+                if let Err(err)=self.send_data(data){
+                    self.link.respond(id, AgentOutput::Reply(request,Err(err)));
+                    return;
                 }
+                let timeout = TimeoutService::new().spawn(
+                    REQUEST_TIMEOUT,
+                    self.link.callback(move |_|Msg::RequestTimeout(request_id))
+                );
+                self.pending.insert(request_id,PendingRequest{
+                    handler:id,
+                    request,
+                    _timeout:timeout
+                });
             }
             Self::Input::SaveFile(file)=>{
-                for subs in &self.subscribers{
-                    self.link.respond(subs.clone(), AgentOutput::SaveFile(file.clone()))
-                }
+                self.respond_all(||AgentOutput::SaveFile(file.clone()));
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn data(command:&str,value:&str,args:&[&str],id:Option<u64>)->TransferData{
+        TransferData{
+            command:command.to_string(),
+            value:value.to_string(),
+            args:args.iter().map(|arg|arg.to_string()).collect(),
+            id
+        }
+    }
+
+    fn fields(data:&TransferData)->(&str,&str,Vec<&str>,Option<u64>){
+        (&data.command,&data.value,data.args.iter().map(String::as_str).collect(),data.id)
+    }
+
+    /// Decodes what `data` looks like on the wire.
+    fn over_the_wire(data:&TransferData)->TransferData{
+        serde_json::from_str(&serde_json::to_string(data).unwrap()).unwrap()
+    }
+
+    fn entry()->FsEntry{
+        FsEntry{is_dir:false,name:"main.rs".to_string(),path:"/src/main.rs".to_string()}
+    }
+
+    #[test]
+    fn requests_map_to_commands(){
+        let file = serde_json::to_string(&entry()).unwrap();
+        let cases = [
+            (Request::Auth("secret".to_string()),("auth","token",vec!["secret"])),
+            (Request::Encoding(vec!["msgpack".to_string(),"json".to_string()]),("encoding","negotiate",vec!["msgpack","json"])),
+            (Request::Ping(1.5),("ping","0",vec!["1.5"])),
+            (Request::ProcessNew("t1".to_string()),("process","new",vec!["t1"])),
+            (Request::ProcessList,("process","list",vec![])),
+            (Request::ProcessReplay("t1".to_string()),("process","replay",vec!["t1"])),
+            (Request::ProcessResize{termid:"t1".to_string(),rows:24,cols:80},("process","resize",vec!["t1","24","80"])),
+            (Request::ProcessKill("t1".to_string()),("process","kill",vec!["t1"])),
+            (Request::Exec{termid:"t1".to_string(),input:"ls\n".to_string()},("exec","t1",vec!["ls\n"])),
+            (Request::FsList("/src".to_string()),("fs","list",vec!["/src"])),
+            (Request::FsOpen("/src/main.rs".to_string()),("fs","open",vec!["/src/main.rs"])),
+            (Request::FsNew(entry()),("fs","new",vec![file.as_str()])),
+            (Request::FsNewDir("/src/bin".to_string()),("fs","new_dir",vec!["/src/bin"])),
+            (Request::FsSave{file:entry(),content:"fn main(){}".to_string()},("fs","save",vec![file.as_str(),"fn main(){}"]))
+        ];
+        for (request,(command,value,args)) in cases{
+            let data = over_the_wire(&TransferData::from(request));
+            assert_eq!(fields(&data),(command,value,args,None));
+        }
+    }
+
+    #[test]
+    fn request_id_survives_the_wire(){
+        let mut data = TransferData::from(Request::ProcessList);
+        assert!(!serde_json::to_string(&data).unwrap().contains("\"id\""));
+        data.id=Some(7);
+        assert_eq!(over_the_wire(&data).id,Some(7));
+    }
+
+    #[test]
+    fn messages_map_to_responses(){
+        let file = serde_json::to_string(&entry()).unwrap();
+        let cases = [
+            (data("auth","ok",&[],None),Response::AuthOk),
+            (data("auth","failed",&["bad token"],None),Response::AuthFailed("bad token".to_string())),
+            (data("encoding","msgpack",&[],None),Response::Encoding("msgpack".to_string())),
+            (data("ping","0",&["0","1.5"],None),Response::Pong(1.5)),
+            (data("process","list",&["t1","t2"],None),Response::ProcessList(vec!["t1".to_string(),"t2".to_string()])),
+            (data("exec","t1",&["ls\n","total 0"],None),Response::Exec{termid:"t1".to_string(),output:"total 0".to_string()}),
+            (data("fs","list",&[file.as_str(),"not json"],None),Response::FsList(vec![entry()])),
+            (data("fs","open",&["/src/main.rs","fn main(){}"],Some(3)),Response::FsOpen{path:"/src/main.rs".to_string(),content:"fn main(){}".to_string()}),
+            (data("fs","save",&[file.as_str()],Some(4)),Response::FsSave(entry()))
+        ];
+        for (data,response) in cases{
+            assert_eq!(Response::try_from(over_the_wire(&data)).unwrap(),response);
+        }
+    }
+
+    #[test]
+    fn frames_decode_with_the_negotiated_encoding(){
+        let sent = data("exec","t1",&["","\x1b[1mhi\x1b[0m"],Some(9));
+        let named = rmp_serde::to_vec_named(&sent).unwrap();
+        let compact = rmp_serde::to_vec(&sent).unwrap();
+        for bytes in [named.clone(),compact]{
+            assert_eq!(fields(&FrameEncoding::MsgPack.decode(Frame::Binary(bytes)).unwrap()),fields(&sent));
+        }
+        let json = serde_json::to_string(&sent).unwrap();
+        assert_eq!(fields(&FrameEncoding::Json.decode(Frame::Binary(json.clone().into_bytes())).unwrap()),fields(&sent));
+        // Text frames are JSON whatever was negotiated.
+        assert_eq!(fields(&FrameEncoding::MsgPack.decode(Frame::Text(json)).unwrap()),fields(&sent));
+        assert!(FrameEncoding::Json.decode(Frame::Binary(named)).is_err());
+    }
+
+    #[test]
+    fn unknown_messages_are_handed_back(){
+        for data in [data("fs","open",&["/only/path"],Some(1)),data("weather","today",&[],None)]{
+            let back = Response::try_from(data.clone()).unwrap_err();
+            assert_eq!(fields(&back),fields(&data));
+        }
+    }
+}
@@ -1,55 +1,126 @@
-use crate::socket_agent::TransferData;
-use web_sys::HtmlElement;
-use yew::prelude::*;
-use super::terminal_src::Terminal;
+use std::collections::VecDeque;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
+use web_sys::{HtmlElement,NotificationPermission};
+use yew::prelude::*;
+use yew::services::timeout::{TimeoutService,TimeoutTask};
+use super::terminal_src::{Alerts,MouseInput,OscNotification,Terminal,TerminalCallbacks};
+use super::recording::{download,ExportFormat,Recording};
+
+use super::socket_agent::{AgentInput,AgentOutput,Request,Response,SocketAgent};
 
-use super::socket_agent::{AgentInput,AgentOutput,SocketAgent};
+/// How long the pane flashes for a bell.
+const FLASH_MS:u64 = 150;
+/// Delay before matches are recomputed after new output.
+const SEARCH_REFRESH_MS:u64 = 300;
+/// Replay output is considered done after this long without more output.
+const REPLAY_QUIET_MS:u64 = 500;
+/// Notifications kept while waiting for the user to allow them.
+const PENDING_NOTIFICATIONS:usize = 16;
+
+/// Shows an OSC notification on the desktop, permission must be granted.
+fn notify(default_title:&str,notification:&OscNotification){
+    let mut options = web_sys::NotificationOptions::new();
+    options.body(&notification.body);
+    let title = notification.title.as_deref().unwrap_or(default_title);
+    if let Err(err)=web_sys::Notification::new_with_options(title,&options){
+        log::error!("Cannot show notification {:?}",err);
+    }
+}
 
 pub struct TerminalComp{
     link:ComponentLink<Self>,
     divref:NodeRef,
     props:Props,
     terminal:Option<Terminal>,
-    socket_agent:Box<dyn yew::Bridge<SocketAgent>>
+    /// Rows and columns last sent to the backend process.
+    size:(u16,u16),
+    socket_agent:Box<dyn yew::Bridge<SocketAgent>>,
+    search:String,
+    match_count:usize,
+    current_match:Option<usize>,
+    /// Last recording, still growing while `is_recording`.
+    recording:Option<Recording>,
+    is_recording:bool,
+    /// Exports whose object URL is still alive, oldest first.
+    downloads:VecDeque<TimeoutTask>,
+    /// Set while the pane flashes for a bell.
+    flash:Option<TimeoutTask>,
+    /// Pending refresh of the search after new output.
+    search_refresh:Option<TimeoutTask>,
+    /// Notifications that arrived before the user answered the permission
+    /// prompt, oldest first.
+    pending_notifications:VecDeque<OscNotification>,
+    /// Set while a reattached terminal is still receiving its replayed
+    /// screen, whose bells and notifications are stale.
+    replaying:Option<TimeoutTask>
 }
 
 #[derive(Properties,Clone,PartialEq)]
 pub struct Props{
     pub termid:String,
     pub thost:super::ide_home::TerminalHost,
-    pub title_change:Callback<String>
+    /// Lines of history kept above the screen, fixed once the terminal is mounted.
+    pub scrollback:usize,
+    pub title_change:Callback<String>,
+    /// A bell or notification arrived while this terminal is not focused.
+    pub on_bell:Callback<()>
 }
 
 pub enum Msg{
     Input(String),
     Write(String),
+    Scroll(isize),
+    Resize,
+    Mouse(MouseInput),
+    Copy,
+    Search(String),
+    FindNext(bool),
+    ClearSearch,
+    ToggleRecording,
+    Export(ExportFormat),
+    ExportDone,
+    FlashDone,
+    RefreshSearch,
+    ReplayDone,
+    RequestNotifications,
+    NotificationPermission,
     Ignore
 }
 
-fn get_max_text(el:&web_sys::Element)->Result<u16,JsValue>{
-    let window = yew::utils::window();
-    let style=window.get_computed_style(el)?;
-    if let Some(style)=style{
-        // let reg = regex::Regex::new(r"\d+").expect("Not regex");
-        let font_size = style.get_property_value("font-size")?;
-        let font_size = &font_size[..font_size.len()-2];
-        let width = style.get_property_value("width")?;
-        let width = &width[..width.len()-2];
-        let font_size:u16 = {
-            // let capt = reg.captures(&font_size).expect("Cant capture");
-            (font_size.parse::<f64>().expect("Not u32") * 0.625).floor() as u16
-        };
-        let width:u16 = {
-            // let capt = reg.captures(&width).expect("Cant capture");
-            width.parse().expect("Not u32")
-        };
+impl TerminalComp{
+    fn send_size(&mut self){
+        self.socket_agent.send(AgentInput::Send(Request::ProcessResize{
+            termid:self.props.termid.clone(),
+            rows:self.size.0,
+            cols:self.size.1
+        }));
+    }
 
-        return Ok(width/font_size)
+    /// Restarts the wait for the end of the replayed screen.
+    fn replay_timeout(&self)->TimeoutTask{
+        TimeoutService::new().spawn(
+            std::time::Duration::from_millis(REPLAY_QUIET_MS),
+            self.link.callback(|_|Msg::ReplayDone)
+        )
+    }
 
+    /// Sizes the backend process and moves it into the project directory,
+    /// running `init_cmd` first when one is given.
+    fn start_session(&mut self,init_cmd:&str){
+        self.send_size();
+
+        let data;
+        if init_cmd.is_empty(){
+            data=format!("cd {} \n",self.props.thost.init_dir);
+        }else{
+            data=format!("cd / && {} && cd {} \n",init_cmd,self.props.thost.init_dir)
+        }
+
+        self.socket_agent.send(AgentInput::Send(Request::Exec{
+            termid:self.props.termid.clone(),
+            input:data
+        }));
     }
-    Err(JsValue::from_str("cant get computed style"))
 }
 
 impl Component for TerminalComp{
@@ -63,11 +134,9 @@ impl Component for TerminalComp{
 
         let term_id = props.termid.clone();
         let socket_agent = SocketAgent::bridge(link.callback(move |data:AgentOutput|{
-            if let AgentOutput::SocketMessage(msg)=data{
-                if msg.command=="exec" && msg.value==term_id.clone(){
-                    if let Some(out)=msg.args.get(1){
-                        return Msg::Write(out.to_string());
-                    }
+            if let AgentOutput::Response(Response::Exec{termid,output})=data{
+                if termid==term_id{
+                    return Msg::Write(output);
                 }
             }
             Msg::Ignore
@@ -77,7 +146,18 @@ impl Component for TerminalComp{
             divref:NodeRef::default(),
             props,
             terminal:None,
-            socket_agent
+            size:(24,80),
+            socket_agent,
+            search:String::new(),
+            match_count:0,
+            current_match:None,
+            recording:None,
+            is_recording:false,
+            downloads:VecDeque::new(),
+            flash:None,
+            search_refresh:None,
+            pending_notifications:VecDeque::new(),
+            replaying:None
         }
     }
 
@@ -85,23 +165,191 @@ impl Component for TerminalComp{
         match msg{
             Self::Message::Input(data)=>{
                 log::info!("input {}",data);
-                self.socket_agent.send(AgentInput::SendData(
-                    TransferData{
-                        command:"exec".to_string(),
-                        value:self.props.termid.clone(),
-                        args:vec![data]
-                    }
-                ))
+                if let Some(term)=&mut self.terminal{
+                    term.scroll_to_bottom();
+                }
+                self.socket_agent.send(AgentInput::Send(Request::Exec{
+                    termid:self.props.termid.clone(),
+                    input:data
+                }))
             }
             Self::Message::Write(data)=>{
+                if self.is_recording{
+                    if let Some(recording)=&mut self.recording{
+                        recording.push(&data);
+                    }
+                }
                 if let Some(term)=&mut self.terminal{
                     term.write(&data);
+                    // Switching screens drops the search, and output makes it stale.
+                    self.match_count=term.match_count();
+                    self.current_match=term.current_match();
+                    if term.search_stale() && self.search_refresh.is_none(){
+                        self.search_refresh=Some(TimeoutService::new().spawn(
+                            std::time::Duration::from_millis(SEARCH_REFRESH_MS),
+                            self.link.callback(|_|Msg::RefreshSearch)
+                        ));
+                    }
                     let title = term.get_title();
-                    if !title.is_empty() && self.props.thost.title == title{
+                    if !title.is_empty() && self.props.thost.title != title{
                         self.props.title_change.emit(title);
                     }
+                    let mut alerts = term.take_alerts();
+                    if self.replaying.is_some(){
+                        self.replaying=Some(self.replay_timeout());
+                        alerts=Alerts::default();
+                    }
+                    let is_active = self.props.thost.is_active;
+                    if alerts.bell{
+                        if is_active{
+                            self.flash=Some(TimeoutService::new().spawn(
+                                std::time::Duration::from_millis(FLASH_MS),
+                                self.link.callback(|_|Msg::FlashDone)
+                            ));
+                        }else{
+                            self.props.on_bell.emit(());
+                        }
+                    }
+                    if !alerts.notifications.is_empty(){
+                        let page_hidden = yew::utils::document().hidden();
+                        if !is_active || page_hidden{
+                            match web_sys::Notification::permission(){
+                                NotificationPermission::Granted=>{
+                                    for notification in &alerts.notifications{
+                                        notify(&self.props.thost.title,notification);
+                                    }
+                                }
+                                // Only a click may ask, so keep them until the user allows them.
+                                NotificationPermission::Default=>{
+                                    self.pending_notifications.extend(alerts.notifications);
+                                    while self.pending_notifications.len()>PENDING_NOTIFICATIONS{
+                                        self.pending_notifications.pop_front();
+                                    }
+                                }
+                                _=>{}
+                            }
+                        }
+                        if !is_active{
+                            self.props.on_bell.emit(());
+                        }
+                    }
                 }
             }
+            Self::Message::FlashDone=>{
+                self.flash=None;
+            }
+            Self::Message::RefreshSearch=>{
+                self.search_refresh=None;
+                if let Some(term)=&mut self.terminal{
+                    term.refresh_search();
+                    self.match_count=term.match_count();
+                    self.current_match=term.current_match();
+                }
+            }
+            Self::Message::ReplayDone=>{
+                self.replaying=None;
+                return false;
+            }
+            Self::Message::RequestNotifications=>{
+                let answered = self.link.callback(|_|Msg::NotificationPermission);
+                let result = web_sys::Notification::request_permission().map(|promise|{
+                    let closure = Closure::once(move |_:JsValue|answered.emit(()));
+                    let _ = promise.then(&closure);
+                    closure.forget();
+                });
+                if let Err(err)=result{
+                    log::error!("Cannot request notification permission {:?}",err);
+                }
+                return false;
+            }
+            Self::Message::NotificationPermission=>{
+                match web_sys::Notification::permission(){
+                    NotificationPermission::Granted=>{
+                        for notification in self.pending_notifications.drain(..){
+                            notify(&self.props.thost.title,&notification);
+                        }
+                    }
+                    NotificationPermission::Denied=>self.pending_notifications.clear(),
+                    _=>{}
+                }
+            }
+            Self::Message::Scroll(lines)=>{
+                if let Some(term)=&mut self.terminal{
+                    term.scroll(lines);
+                }
+                return false;
+            }
+            Self::Message::Resize=>{
+                let resized = self.terminal.as_mut().and_then(|term|term.fit());
+                if let Some(size)=resized{
+                    self.size=size;
+                    self.send_size();
+                    if self.is_recording{
+                        if let Some(recording)=&mut self.recording{
+                            recording.resize(size.0,size.1);
+                        }
+                    }
+                }
+                return false;
+            }
+            Self::Message::Mouse(input)=>{
+                let report = self.terminal.as_mut().and_then(|term|term.mouse(input));
+                if let Some(data)=report{
+                    self.socket_agent.send(AgentInput::Send(Request::Exec{
+                        termid:self.props.termid.clone(),
+                        input:data
+                    }));
+                }
+                return false;
+            }
+            Self::Message::Copy=>{
+                if let Some(term)=&self.terminal{
+                    term.copy_selection();
+                }
+                return false;
+            }
+            Self::Message::Search(query)=>{
+                if let Some(term)=&mut self.terminal{
+                    self.match_count=term.find(&query);
+                    self.current_match=self.match_count.checked_sub(1);
+                }
+                self.search=query;
+            }
+            Self::Message::FindNext(forward)=>{
+                if let Some(term)=&mut self.terminal{
+                    self.current_match=term.find_next(forward);
+                }
+            }
+            Self::Message::ClearSearch=>{
+                if let Some(term)=&mut self.terminal{
+                    term.clear_search();
+                    term.scroll_to_bottom();
+                }
+                self.search.clear();
+                self.match_count=0;
+                self.current_match=None;
+            }
+            Self::Message::ToggleRecording=>{
+                if !self.is_recording{
+                    self.recording=Some(Recording::start(self.size.0,self.size.1));
+                }
+                self.is_recording = !self.is_recording;
+            }
+            Self::Message::Export(format)=>{
+                if let Some(recording)=&self.recording{
+                    let filename = format!("{}-{}.{}",self.props.termid,recording.started as u64,format.extension());
+                    match download(&filename,&recording.export(format),format.mime(),self.link.callback(|_|Msg::ExportDone)){
+                        Ok(task)=>self.downloads.push_back(task),
+                        Err(err)=>log::error!("Cannot export recording {:?}",err)
+                    }
+                }
+                return false;
+            }
+            Self::Message::ExportDone=>{
+                // Every export waits as long, so they finish in order.
+                self.downloads.pop_front();
+                return false;
+            }
             Self::Message::Ignore=>{
                 return false;
             }
@@ -113,7 +361,17 @@ impl Component for TerminalComp{
     fn change(&mut self,_props: Self::Properties)->bool{
         // log::debug!("term {} changed {}",self.props.termid,_props.termid);
         if self.props != _props{
+            let respawned = self.props.thost.generation != _props.thost.generation;
+            let focused = !self.props.thost.is_active && _props.thost.is_active;
             self.props = _props;
+            if focused{
+                if let Some(term)=&self.terminal{
+                    term.focus();
+                }
+            }
+            if respawned{
+                self.start_session("");
+            }
             true
         }else{
             false
@@ -130,38 +388,28 @@ impl Component for TerminalComp{
         // log::debug!("new term {} mounted",self.props.termid);
 
         let divel = self.divref.cast::<HtmlElement>().expect("not htmlelement");
-        let el = self.divref.cast::<web_sys::Element>().expect("Not Element");
-        let max_line = get_max_text(&el).unwrap_or(24) as u16 -2;
-        log::info!("detected term cols {}",max_line);
-        self.socket_agent.send(AgentInput::SendData(
-            TransferData{
-                command:"process".to_string(),
-                value:"resize".to_string(),
-                args:vec![
-                    self.props.termid.clone(),
-                    "80".to_string(),
-                    format!("{}",max_line)
-                ]
-            }
-        ));
-
-        match Terminal::new(divel,self.link.callback(|data|Msg::Input(data)),max_line){
-            Ok(term)=>{
-                let data;
-                if self.props.thost.init_cmd.is_empty(){
-                    data=format!("cd {} \n",self.props.thost.init_dir);
-                }else{
-                    data=format!("cd / && {} && cd {} \n",self.props.thost.init_cmd,self.props.thost.init_dir)
-                }
 
-                self.socket_agent.send(AgentInput::SendData(
-                    TransferData{
-                        command:"exec".to_string(),
-                        value:self.props.termid.clone(),
-                        args:vec![data]
-                    }
-                ));
+        let callbacks = TerminalCallbacks{
+            on_data:self.link.callback(Msg::Input),
+            on_scroll:self.link.callback(Msg::Scroll),
+            on_resize:self.link.callback(|_|Msg::Resize),
+            on_mouse:self.link.callback(Msg::Mouse),
+            on_copy:self.link.callback(|_|Msg::Copy)
+        };
+        match Terminal::new(divel,callbacks,self.props.scrollback){
+            Ok(mut term)=>{
+                term.fit();
+                self.size=term.size();
+                log::info!("detected term size {:?}",self.size);
                 self.terminal=Some(term);
+                if self.props.thost.reattach{
+                    self.send_size();
+                    self.replaying=Some(self.replay_timeout());
+                    self.socket_agent.send(AgentInput::Send(Request::ProcessReplay(self.props.termid.clone())));
+                }else{
+                    let init_cmd = self.props.thost.init_cmd.clone();
+                    self.start_session(&init_cmd);
+                }
 
             }
             Err(err)=>{
@@ -174,8 +422,98 @@ impl Component for TerminalComp{
 
     fn view(&self)->Html{
 
+        let count = match self.current_match{
+            Some(idx)=>format!("{}/{}",idx+1,self.match_count),
+            None if !self.search.is_empty()=>"0/0".to_string(),
+            None=>String::new()
+        };
         html!{
-            <div style="overflow:auto;" ref=self.divref.clone()></div>
+            <div class={
+                if self.flash.is_some(){
+                    "term-flash"
+                }else{
+                    ""
+                }
+            } style="height:100%;display:flex;flex-direction:column;">
+                <div class="field has-addons is-marginless">
+                    <div class="control">
+                        <input class="input is-small" type="text" placeholder="Search" value=&self.search
+                            oninput=self.link.callback(|e:InputData|Msg::Search(e.value))
+                            onkeydown=self.link.callback(|e:KeyboardEvent|match e.key().as_str(){
+                                "Enter"=>Msg::FindNext(!e.shift_key()),
+                                "Escape"=>Msg::ClearSearch,
+                                _=>Msg::Ignore
+                            })></input>
+                    </div>
+                    <div class="control">
+                        <button class="button is-small" title="Previous match" onclick=self.link.callback(|_|Msg::FindNext(false))>
+                            <span class="icon is-small"><i class="fas fa-chevron-up"></i></span>
+                        </button>
+                    </div>
+                    <div class="control">
+                        <button class="button is-small" title="Next match" onclick=self.link.callback(|_|Msg::FindNext(true))>
+                            <span class="icon is-small"><i class="fas fa-chevron-down"></i></span>
+                        </button>
+                    </div>
+                    <div class="control">
+                        <button class="button is-small" title="Clear search" onclick=self.link.callback(|_|Msg::ClearSearch)>
+                            <span class="icon is-small"><i class="fas fa-times"></i></span>
+                        </button>
+                    </div>
+                    <p class="help" style="margin-left:0.5em;">{count}</p>
+                    {
+                        if self.pending_notifications.is_empty(){
+                            html!{}
+                        }else{
+                            html!{
+                                <div class="control" style="margin-left:0.5em;">
+                                    <button class="button is-small is-warning" title="Allow desktop notifications" onclick=self.link.callback(|_|Msg::RequestNotifications)>
+                                        <span class="icon is-small"><i class="fas fa-bell"></i></span>
+                                        <span>{self.pending_notifications.len()}</span>
+                                    </button>
+                                </div>
+                            }
+                        }
+                    }
+                    <div class="control" style="margin-left:auto;">
+                        <button class={
+                            if self.is_recording{
+                                "button is-small is-danger"
+                            }else{
+                                "button is-small"
+                            }
+                        } title={
+                            if self.is_recording{
+                                "Stop recording"
+                            }else{
+                                "Record output"
+                            }
+                        } onclick=self.link.callback(|_|Msg::ToggleRecording)>
+                            <span class="icon is-small"><i class="fas fa-circle"></i></span>
+                        </button>
+                    </div>
+                    {
+                        if self.recording.is_some(){
+                            html!{
+                                <>
+                                    <div class="control">
+                                        <button class="button is-small" title="Export as plain text" onclick=self.link.callback(|_|Msg::Export(ExportFormat::PlainText))>{"txt"}</button>
+                                    </div>
+                                    <div class="control">
+                                        <button class="button is-small" title="Export with escape sequences" onclick=self.link.callback(|_|Msg::Export(ExportFormat::Ansi))>{"ansi"}</button>
+                                    </div>
+                                    <div class="control">
+                                        <button class="button is-small" title="Export as asciicast v2" onclick=self.link.callback(|_|Msg::Export(ExportFormat::Asciicast))>{"cast"}</button>
+                                    </div>
+                                </>
+                            }
+                        }else{
+                            html!{}
+                        }
+                    }
+                </div>
+                <div style="flex:1;min-height:0;overflow:hidden;" ref=self.divref.clone()></div>
+            </div>
         }
     }
 }
@@ -1,5 +1,8 @@
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash,Hasher};
+use std::cell::Cell;
+use std::rc::Rc;
 use web_sys::HtmlTextAreaElement;
 use wasm_bindgen::JsCast;
 use vt100::Color::*;
@@ -7,7 +10,7 @@ use wasm_bindgen::prelude::*;
 use vt100;
 use js_sys::{Function};
 use web_sys::{HtmlElement};
-use gloo::{events::EventListener};
+use gloo::events::{EventListener,EventListenerOptions};
 use lazy_static::lazy_static;
 
 #[wasm_bindgen]
@@ -15,46 +18,299 @@ extern "C" {
     fn alert(s: &str);
 }
 
+#[wasm_bindgen]
+extern "C" {
+    type ResizeObserver;
+
+    #[wasm_bindgen(constructor)]
+    fn new(callback:&Function)->ResizeObserver;
+
+    #[wasm_bindgen(method)]
+    fn observe(this:&ResizeObserver,target:&web_sys::Element);
+
+    #[wasm_bindgen(method)]
+    fn disconnect(this:&ResizeObserver);
+}
+
+#[wasm_bindgen]
+extern "C" {
+    type Clipboard;
+
+    #[wasm_bindgen(method,js_name=writeText)]
+    fn write_text(this:&Clipboard,data:&str)->js_sys::Promise;
+}
+
+fn clipboard()->Option<Clipboard>{
+    let navigator = web_sys::window()?.navigator();
+    js_sys::Reflect::get(&navigator,&JsValue::from_str("clipboard")).ok()
+        .filter(|clipboard|!clipboard.is_undefined())
+        .map(|clipboard|clipboard.unchecked_into())
+}
+
+/// How a special key is encoded; see `encode_key`.
+enum KeySeq{
+    /// Sent as is, prefixed with ESC when Alt is held.
+    Plain(&'static str),
+    /// `CSI <c>`, or `SS3 <c>` in application cursor mode.
+    Cursor(char),
+    /// `CSI <n> ~`
+    Tilde(u8),
+    /// `SS3 <c>`
+    Ss3(char)
+}
+
 lazy_static! {
-    static ref KEYCODEMAP: HashMap<String, String> = {
+    static ref KEYCODEMAP: HashMap<&'static str, KeySeq> = {
         let mut m = HashMap::new();
-      //   keymaptochar={
-      //     "ArrowUp":String.fromCharCode(27)+String.fromCharCode(91)+String.fromCharCode(65),
-      //     "ArrowDown":String.fromCharCode(27)+String.fromCharCode(91)+String.fromCharCode(66),
-      //     "ArrowRight":String.fromCharCode(27)+String.fromCharCode(91)+String.fromCharCode(67),
-      //     "ArrowLeft":String.fromCharCode(27)+String.fromCharCode(91)+String.fromCharCode(68),
-      //     "Backspace":String.fromCharCode(127),
-      //     "Escape":String.fromCharCode(27),
-      //     "Enter":String.fromCharCode(13)
-      // };
-        m.insert("ArrowUp".to_owned(),String::from_utf8(vec![27,91,65]).expect("not utf8"));
-        m.insert("ArrowDown".to_owned(),String::from_utf8(vec![27,91,66]).expect("not utf8"));
-        m.insert("ArrowRight".to_owned(),String::from_utf8(vec![27,91,67]).expect("not utf8"));
-        m.insert("ArrowLeft".to_owned(),String::from_utf8(vec![27,91,68]).expect("not utf8"));
-        m.insert("Backspace".to_owned(),String::from_utf8(vec![127]).expect("not utf8"));
-        m.insert("Escape".to_owned(),String::from_utf8(vec![27]).expect("not utf8"));
-        // m.insert("Enter".to_owned(),String::from_utf8(vec![13]).expect("not utf8"));
+        m.insert("ArrowUp",KeySeq::Cursor('A'));
+        m.insert("ArrowDown",KeySeq::Cursor('B'));
+        m.insert("ArrowRight",KeySeq::Cursor('C'));
+        m.insert("ArrowLeft",KeySeq::Cursor('D'));
+        m.insert("Home",KeySeq::Cursor('H'));
+        m.insert("End",KeySeq::Cursor('F'));
+        m.insert("Insert",KeySeq::Tilde(2));
+        m.insert("Delete",KeySeq::Tilde(3));
+        m.insert("PageUp",KeySeq::Tilde(5));
+        m.insert("PageDown",KeySeq::Tilde(6));
+        m.insert("F1",KeySeq::Ss3('P'));
+        m.insert("F2",KeySeq::Ss3('Q'));
+        m.insert("F3",KeySeq::Ss3('R'));
+        m.insert("F4",KeySeq::Ss3('S'));
+        m.insert("F5",KeySeq::Tilde(15));
+        m.insert("F6",KeySeq::Tilde(17));
+        m.insert("F7",KeySeq::Tilde(18));
+        m.insert("F8",KeySeq::Tilde(19));
+        m.insert("F9",KeySeq::Tilde(20));
+        m.insert("F10",KeySeq::Tilde(21));
+        m.insert("F11",KeySeq::Tilde(23));
+        m.insert("F12",KeySeq::Tilde(24));
+        m.insert("Backspace",KeySeq::Plain("\x7f"));
+        m.insert("Escape",KeySeq::Plain("\x1b"));
+        m.insert("Tab",KeySeq::Plain("\t"));
+        // Enter reaches the terminal through the textarea input event.
 
         m
     };
 }
 
+/// Terminal modes the input listeners need, refreshed after every write.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct InputModes{
+    pub application_cursor:bool,
+    pub bracketed_paste:bool,
+    pub mouse_mode:vt100::MouseProtocolMode,
+    pub mouse_encoding:vt100::MouseProtocolEncoding
+}
+
+/// Control character produced by Ctrl+`ch`, following xterm.
+fn ctrl_code(ch:char)->Option<char>{
+    let code = match ch{
+        'a'..='z'=>ch as u8-b'a'+1,
+        'A'..='Z'=>ch as u8-b'A'+1,
+        '@'|' '|'2'=>0,
+        '['|'3'=>27,
+        '\\'|'4'=>28,
+        ']'|'5'=>29,
+        '^'|'6'=>30,
+        '_'|'-'|'7'=>31,
+        '?'|'8'=>127,
+        _=>return None
+    };
+    Some(code as char)
+}
+
+/// Bytes to send for a keydown, or `None` when the key is left to the
+/// browser (printable text arrives through the textarea input event).
+pub fn encode_key(event:&web_sys::KeyboardEvent,modes:InputModes)->Option<String>{
+    let key = event.key();
+    let (shift,meta) = (event.shift_key(),event.meta_key());
+    // AltGr arrives as Ctrl+Alt on Windows; what it types ('@', '[', '\'
+    // on many layouts) is text for the input event, not a control key.
+    let (alt,ctrl) = if event.get_modifier_state("AltGraph"){
+        (false,false)
+    }else{
+        (event.alt_key(),event.ctrl_key())
+    };
+    // xterm modifier parameter: 1 + Shift + 2*Alt + 4*Ctrl + 8*Meta
+    let modifier = 1+shift as u8+2*alt as u8+4*ctrl as u8+8*meta as u8;
+    if let Some(seq)=KEYCODEMAP.get(key.as_str()){
+        return Some(match seq{
+            KeySeq::Plain(_) if key=="Tab" && shift=>"\x1b[Z".to_string(),
+            KeySeq::Plain(text) if alt || meta=>format!("\x1b{}",text),
+            KeySeq::Plain(text)=>text.to_string(),
+            KeySeq::Cursor(c) if modifier>1=>format!("\x1b[1;{}{}",modifier,c),
+            KeySeq::Cursor(c) if modes.application_cursor=>format!("\x1bO{}",c),
+            KeySeq::Cursor(c)=>format!("\x1b[{}",c),
+            KeySeq::Tilde(n) if modifier>1=>format!("\x1b[{};{}~",n,modifier),
+            KeySeq::Tilde(n)=>format!("\x1b[{}~",n),
+            KeySeq::Ss3(c) if modifier>1=>format!("\x1b[1;{}{}",modifier,c),
+            KeySeq::Ss3(c)=>format!("\x1bO{}",c)
+        });
+    }
+    let mut chars = key.chars();
+    let ch = match (chars.next(),chars.next()){
+        (Some(ch),None)=>ch,
+        // Modifier keys alone, dead keys and unknown named keys.
+        _=>return None
+    };
+    // Cmd (or the Windows key) with a character is a browser or system
+    // shortcut such as copy and paste.
+    if meta{
+        return None;
+    }
+    if ctrl{
+        // Ctrl+Shift+letter is kept for the browser side (copy, paste).
+        if shift && ch.is_ascii_alphabetic(){
+            return None;
+        }
+        let code = ctrl_code(ch)?;
+        return Some(if alt { format!("\x1b{}",code) } else { code.to_string() });
+    }
+    if alt{
+        return Some(format!("\x1b{}",ch));
+    }
+    None
+}
+
+/// What to send for pasted `text`. ESC is dropped so the text can neither
+/// end bracketed paste early with its own `ESC [201~` nor smuggle in other
+/// sequences, and line endings become the CR that Enter sends.
+fn paste_input(text:&str,bracketed:bool)->String{
+    let text = text.replace("\r\n","\r").replace('\n',"\r").replace('\x1b',"");
+    if bracketed{
+        format!("\x1b[200~{}\x1b[201~",text)
+    }else{
+        text
+    }
+}
+
+/// Lines moved by Shift+PageUp/PageDown.
+const SCROLL_PAGE:usize = 12;
+/// Measured to get the width of one monospace cell.
+const PROBE_TEXT:&str = "WWWWWWWWWW";
+const MIN_ROWS:u16 = 2;
+const MIN_COLS:u16 = 10;
+
+/// What vt100 reports through callbacks rather than screen state.
+#[derive(Default)]
+struct ParserEvents{
+    /// Bells rung so far, audible and visual alike.
+    bells:usize,
+    title:String
+}
+
+impl vt100::Callbacks for ParserEvents{
+    fn audible_bell(&mut self,_:&mut vt100::Screen){
+        self.bells+=1;
+    }
+
+    fn visual_bell(&mut self,_:&mut vt100::Screen){
+        self.bells+=1;
+    }
+
+    fn set_window_title(&mut self,_:&mut vt100::Screen,title:&[u8]){
+        self.title=String::from_utf8_lossy(title).into_owned();
+    }
+}
+
 #[wasm_bindgen]
 pub struct VtParser {
-    parser:vt100::Parser,
+    parser:vt100::Parser<ParserEvents>,
     token: f64,
+    cursor_shape:CursorShape,
+    /// Set by DECSET 47/1049; vt100 switches screens but does not say so.
+    alternate_screen:bool,
+    /// Unfinished escape sequence at the end of the last chunk, kept for
+    /// `scan_sequences`.
+    pending:String,
+    /// Unterminated OSC at the end of the last chunk.
+    osc_pending:String,
+    notifications:Vec<OscNotification>,
+}
+
+/// Longest unterminated OSC kept across chunks; anything longer is dropped.
+const OSC_PENDING_LIMIT:usize = 4096;
+/// Longest unfinished CSI kept across chunks for `scan_sequences`.
+const CSI_PENDING_LIMIT:usize = 32;
+
+/// Desktop notification requested with OSC 9 or OSC 777.
+#[derive(Clone,Debug,PartialEq)]
+pub struct OscNotification{
+    pub title:Option<String>,
+    pub body:String
+}
+
+impl OscNotification{
+    /// `9;<body>` or `777;notify;<title>;<body>`. ConEmu's `9;<n>;..`
+    /// subcommands (progress, working directory and so on) are not
+    /// notifications.
+    fn parse(osc:&str)->Option<OscNotification>{
+        if let Some(body)=osc.strip_prefix("9;"){
+            let digits = body.find(|c:char|!c.is_ascii_digit()).unwrap_or(body.len());
+            if digits>0 && body[digits..].starts_with(';'){
+                return None;
+            }
+            return Some(OscNotification{
+                title:None,
+                body:body.to_string()
+            });
+        }
+        if let Some(notify)=osc.strip_prefix("777;notify;"){
+            let mut parts = notify.splitn(2,';');
+            let title = parts.next().unwrap_or_default().to_string();
+            return Some(OscNotification{
+                title:Some(title).filter(|title|!title.is_empty()),
+                body:parts.next().unwrap_or_default().to_string()
+            });
+        }
+        None
+    }
+}
+
+/// Set by DECSCUSR (`CSI Ps SP q`), which vt100 does not track.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum CursorShape{
+    Block,
+    Underline,
+    Bar
+}
+
+impl CursorShape{
+    fn from_param(param:&str)->Option<CursorShape>{
+        match param{
+            ""|"0"|"1"|"2"=>Some(CursorShape::Block),
+            "3"|"4"=>Some(CursorShape::Underline),
+            "5"|"6"=>Some(CursorShape::Bar),
+            _=>None
+        }
+    }
+
+    fn class(self)->&'static str{
+        match self{
+            CursorShape::Block=>"term-cursor",
+            CursorShape::Underline=>"term-cursor term-cursor-underline",
+            CursorShape::Bar=>"term-cursor term-cursor-bar"
+        }
+    }
 }
 
 #[wasm_bindgen]
 impl VtParser{
-    pub fn new(row:u16,cols:u16,token:f64)->VtParser{
+    pub fn new(row:u16,cols:u16,scrollback:usize,token:f64)->VtParser{
         VtParser{
-            parser:vt100::Parser::new(row,cols,0),
-            token
+            parser:vt100::Parser::new_with_callbacks(row,cols,scrollback,ParserEvents::default()),
+            token,
+            cursor_shape:CursorShape::Block,
+            alternate_screen:false,
+            pending:String::new(),
+            osc_pending:String::new(),
+            notifications:vec![]
         }
     }
 
     pub fn parse(&mut self,vttext:&str){
+        self.scan_sequences(vttext);
+        self.scan_notifications(vttext);
         self.parser.process(vttext.as_bytes());
     }
 
@@ -62,73 +318,624 @@ impl VtParser{
         self.parser.screen().contents()
     }
 
-    fn termcolor_to_htmlcolor(color:vt100::Color)->String{
-        match color{
-            Default=>"".to_owned(),
-            Idx(index)=>{
-                match index {
-                    0=>"black".to_string(),
-                    1=>"red".to_string(),
-                    2=>"green".to_string(),
-                    3=>"yellow".to_string(),
-                    4=>"blue".to_string(),
-                    5=>"violet".to_string(),
-                    6=>"aqua".to_string(),
-                    7=>"white".to_string(),
-                    _ => "".to_string()
+    pub fn html_content(&self)->String{
+        let mut outcontent = String::new();
+        for row in 0..self.parser.screen().size().0{
+            outcontent=outcontent+&self.row_html(row,&[])+"<br>"
+        }
+        outcontent
+    }
+}
+
+impl VtParser{
+    pub fn size(&self)->(u16,u16){
+        self.parser.screen().size()
+    }
+
+    /// How many lines the view is currently scrolled back.
+    pub fn scroll_offset(&self)->usize{
+        self.parser.screen().scrollback()
+    }
+
+    fn scan_notifications(&mut self,vttext:&str){
+        let text = std::mem::take(&mut self.osc_pending)+vttext;
+        let mut rest = text.as_str();
+        while let Some(start)=rest.find("\x1b]"){
+            let body = &rest[start+2..];
+            // Terminated by BEL or ST (ESC \).
+            let (end,terminator) = match (body.find('\x07'),body.find("\x1b\\")){
+                (Some(bel),Some(st)) if st<bel=>(st,2),
+                (Some(bel),_)=>(bel,1),
+                (None,Some(st))=>(st,2),
+                (None,None)=>{
+                    if rest.len()-start<=OSC_PENDING_LIMIT{
+                        self.osc_pending=rest[start..].to_string();
+                    }
+                    return;
                 }
-            },
-            Rgb(r,g,b)=>{
-                format!("{},{},{}",r,g,b)
+            };
+            if let Some(notification)=OscNotification::parse(&body[..end]){
+                self.notifications.push(notification);
             }
+            rest=&body[end+terminator..];
+        }
+        if rest.ends_with('\x1b'){
+            self.osc_pending="\x1b".to_string();
         }
     }
 
-    pub fn html_content(&self)->String{
-        let mut outcontent = String::new();
+    /// Notifications requested since the last call.
+    pub fn take_notifications(&mut self)->Vec<OscNotification>{
+        std::mem::take(&mut self.notifications)
+    }
 
-        let cursor_pos = self.parser.screen().cursor_position();
-        for row in 0..self.parser.screen().size().0{
-            for col in 0..self.parser.screen().size().1{
-                let cell = self.parser.screen().cell(row,col);
-                if let Some(cell) = cell{
-                    if cursor_pos!=(row,col){
-                        outcontent=outcontent+&format!(r#"<font color="{}">{}</font>"#,VtParser::termcolor_to_htmlcolor(cell.fgcolor()),cell.contents());
-                    }else{
-                        let mut cellcon=cell.contents();
-                        if cellcon == "".to_string(){
-                            cellcon=" ".to_owned();
-                        }
-                        outcontent=outcontent+&format!(r#"<font color="{}" id="termcursor" style="background-color:gray">{}</font>"#,VtParser::termcolor_to_htmlcolor(cell.fgcolor()),cellcon);
+    /// Bells rung so far, audible and visual alike.
+    pub fn bell_count(&self)->usize{
+        self.parser.callbacks().bells
+    }
 
+    /// Window title set with OSC 0 or 2.
+    pub fn title(&self)->&str{
+        &self.parser.callbacks().title
+    }
+
+    /// Follows state vt100 keeps to itself: the cursor shape set by DECSCUSR
+    /// (`CSI Ps SP q`), and the alternate screen entered and left with DECSET
+    /// and DECRST 47/1049 (the modes vt100 acts on) and reset by RIS (`ESC c`).
+    fn scan_sequences(&mut self,vttext:&str){
+        let text = std::mem::take(&mut self.pending)+vttext;
+        let mut rest = text.as_str();
+        while let Some(start)=rest.find('\x1b'){
+            let seq = &rest[start+1..];
+            if seq.is_empty(){
+                self.pending="\x1b".to_string();
+                return;
+            }
+            if seq.starts_with('c'){
+                self.cursor_shape=CursorShape::Block;
+                self.alternate_screen=false;
+            }else if let Some(csi)=seq.strip_prefix('['){
+                let private = csi.starts_with('?');
+                let body = if private { &csi[1..] } else { csi };
+                let params_len = body.find(|c:char|!(c.is_ascii_digit() || c==';')).unwrap_or(body.len());
+                let (params,tail) = body.split_at(params_len);
+                if tail.is_empty() || (tail==" " && !private){
+                    // Cut off by the end of the chunk.
+                    if rest.len()-start<=CSI_PENDING_LIMIT{
+                        self.pending=rest[start..].to_string();
+                    }
+                    return;
+                }
+                if private{
+                    let set = match tail.as_bytes()[0]{
+                        b'h'=>Some(true),
+                        b'l'=>Some(false),
+                        _=>None
+                    };
+                    if let Some(set)=set{
+                        if params.split(';').any(|param|param=="47" || param=="1049"){
+                            self.alternate_screen=set;
+                        }
+                    }
+                }else if tail.starts_with(" q"){
+                    if let Some(shape)=CursorShape::from_param(params){
+                        self.cursor_shape=shape;
                     }
                 }
             }
-            outcontent=outcontent+"<br>"
+            rest=seq;
         }
+    }
 
-        outcontent
+    pub fn cursor_shape(&self)->CursorShape{
+        self.cursor_shape
+    }
+
+    pub fn cursor_position(&self)->(u16,u16){
+        self.parser.screen().cursor_position()
+    }
+
+    pub fn alternate_screen(&self)->bool{
+        self.alternate_screen
+    }
+
+    pub fn input_modes(&self)->InputModes{
+        InputModes{
+            application_cursor:self.parser.screen().application_cursor(),
+            bracketed_paste:self.parser.screen().bracketed_paste(),
+            mouse_mode:self.parser.screen().mouse_protocol_mode(),
+            mouse_encoding:self.parser.screen().mouse_protocol_encoding()
+        }
+    }
+
+    /// Text from `start` up to and including `end`, both `(row,col)` on screen.
+    /// Rows are joined with newlines and lose their trailing blanks.
+    pub fn contents_between(&self,start:(u16,u16),end:(u16,u16))->String{
+        let screen = self.parser.screen();
+        let cols = screen.size().1;
+        let mut lines = vec![];
+        for row in start.0..=end.0{
+            let mut col = if row==start.0 { start.1 } else { 0 };
+            let last = if row==end.0 { end.1.min(cols-1) } else { cols-1 };
+            let mut line = String::new();
+            while col<=last{
+                let cell = match screen.cell(row,col){
+                    Some(cell)=>cell,
+                    None=>break
+                };
+                match cell.contents(){
+                    ""=>line.push(' '),
+                    contents=>line.push_str(contents)
+                }
+                col+=if cell.is_wide() { 2 } else { 1 };
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    pub fn set_size(&mut self,rows:u16,cols:u16){
+        self.parser.screen_mut().set_size(rows,cols);
+    }
+
+    pub fn set_scroll_offset(&mut self,offset:usize){
+        self.parser.screen_mut().set_scrollback(offset);
+    }
+
+    /// Number of lines kept above the screen.
+    pub fn history_len(&mut self)->usize{
+        let offset = self.scroll_offset();
+        self.set_scroll_offset(usize::MAX);
+        let len = self.scroll_offset();
+        self.set_scroll_offset(offset);
+        len
+    }
+
+    /// Calls `f` for every line of scrollback and screen, oldest first, with
+    /// the view scrolled so the line is on the given row.
+    fn map_lines<T>(&mut self,mut f:impl FnMut(&vt100::Screen,u16)->T)->Vec<T>{
+        let offset = self.scroll_offset();
+        let rows = self.size().0;
+        let history = self.history_len();
+        let mut lines = Vec::with_capacity(history+rows as usize);
+        // Scrolled back `n` lines, the view starts at history line `history-n`;
+        // step a screen at a time and take the rows still in history.
+        let mut back = history;
+        while back>0{
+            self.set_scroll_offset(back);
+            let shown = back.min(rows as usize);
+            for row in 0..shown as u16{
+                lines.push(f(self.parser.screen(),row));
+            }
+            back-=shown;
+        }
+        self.set_scroll_offset(0);
+        for row in 0..rows{
+            lines.push(f(self.parser.screen(),row));
+        }
+        self.set_scroll_offset(offset);
+        lines
+    }
+
+    /// Scrollback followed by the screen, oldest line first.
+    pub fn lines(&mut self)->Vec<String>{
+        let cols = self.size().1;
+        self.map_lines(|screen,row|screen.rows(0,cols).nth(row as usize).unwrap_or_default())
+    }
+
+    /// Case-insensitive matches of `query` across `lines()`.
+    pub fn search(&mut self,query:&str)->Vec<SearchMatch>{
+        let query:Vec<char> = query.to_lowercase().chars().collect();
+        if query.is_empty(){
+            return vec![];
+        }
+        let cols = self.size().1;
+        let lines = self.map_lines(|screen,row|row_chars(screen,row,cols));
+        let mut matches = vec![];
+        for (line,text) in lines.iter().enumerate(){
+            let mut idx = 0;
+            while idx+query.len()<=text.len(){
+                if text[idx..idx+query.len()].iter().map(|(c,_)|*c).eq(query.iter().cloned()){
+                    matches.push(SearchMatch{
+                        line,
+                        start:text[idx].1.start,
+                        end:text[idx+query.len()-1].1.end
+                    });
+                    idx+=query.len();
+                }else{
+                    idx+=1;
+                }
+            }
+        }
+        matches
+    }
+
+    /// One screen row as HTML. Adjacent cells sharing a style are merged into
+    /// a single span; the cursor cell and wide glyphs always get their own.
+    pub fn row_html(&self,row:u16,marks:&[Mark])->String{
+        let screen = self.parser.screen();
+        // The cursor belongs to the live screen, not to history.
+        let cursor_pos = if screen.scrollback()==0 && !screen.hide_cursor() { Some(screen.cursor_position()) } else { None };
+        let mut out = String::new();
+        let mut run:Option<(CellStyle,String)> = None;
+        let mut skip_continuation = false;
+        for col in 0..screen.size().1{
+            // The cell after a wide glyph is covered by it.
+            if skip_continuation{
+                skip_continuation=false;
+                continue;
+            }
+            let cell = match screen.cell(row,col){
+                Some(cell)=>cell,
+                None=>continue
+            };
+            skip_continuation=cell.is_wide();
+            let mut style = CellStyle::from_cell(cell);
+            style.mark=marks.iter().find(|mark|mark.start<=col && col<mark.end).map(|mark|mark.class);
+            let mut contents = escape_html(cell.contents());
+            if contents.is_empty(){
+                contents=" ".to_string();
+            }
+            if cursor_pos==Some((row,col)){
+                push_run(&mut out,run.take());
+                out+=&format!(r#"<span id="termcursor" class="{}"><span{}>{}</span></span>"#,self.cursor_shape.class(),style.attributes(),contents);
+                continue;
+            }
+            if style.wide{
+                push_run(&mut out,run.take());
+                push_run(&mut out,Some((style,contents)));
+                continue;
+            }
+            match &mut run{
+                Some((run_style,text)) if *run_style==style=>text.push_str(&contents),
+                _=>{
+                    push_run(&mut out,run.take());
+                    run=Some((style,contents));
+                }
+            }
+        }
+        push_run(&mut out,run);
+        out
+    }
+}
+
+/// Lowercased text of a visible row, each char with the cells it came
+/// from, so matches in the text map back to cell columns. A wide glyph
+/// covers two cells and a combining mark shares its base's cell.
+fn row_chars(screen:&vt100::Screen,row:u16,cols:u16)->Vec<(char,std::ops::Range<u16>)>{
+    let mut chars = vec![];
+    let mut col = 0;
+    while col<cols{
+        let cell = match screen.cell(row,col){
+            Some(cell)=>cell,
+            None=>break
+        };
+        let width = if cell.is_wide() { 2 } else { 1 };
+        let contents = cell.contents();
+        let contents = if contents.is_empty() { " " } else { contents };
+        for c in contents.chars().flat_map(char::to_lowercase){
+            chars.push((c,col..col+width));
+        }
+        col+=width;
+    }
+    chars
+}
+
+/// Cell contents are program output; never let them become markup.
+fn escape_html(text:&str)->String{
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars(){
+        match c{
+            '&'=>escaped.push_str("&amp;"),
+            '<'=>escaped.push_str("&lt;"),
+            '>'=>escaped.push_str("&gt;"),
+            '"'=>escaped.push_str("&quot;"),
+            _=>escaped.push(c)
+        }
+    }
+    escaped
+}
+
+fn push_run(out:&mut String,run:Option<(CellStyle,String)>){
+    if let Some((style,text))=run{
+        let attrs = style.attributes();
+        if attrs.is_empty(){
+            out.push_str(&text);
+        }else{
+            out.push_str(&format!("<span{}>{}</span>",attrs,text));
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct SearchMatch{
+    /// Index into `VtParser::lines`.
+    pub line:usize,
+    /// Cell columns `start..end`.
+    pub start:u16,
+    pub end:u16
+}
+
+/// Extra class for columns `start..end` of a row, such as a search hit.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Mark{
+    pub start:u16,
+    pub end:u16,
+    pub class:&'static str
+}
+
+/// Rendering attributes of a cell. Palette colors and text attributes become
+/// `term-*` classes a theme can restyle; 256-color and truecolor values are
+/// inlined since no theme defines them.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct CellStyle{
+    pub fg:vt100::Color,
+    pub bg:vt100::Color,
+    pub bold:bool,
+    pub italic:bool,
+    pub underline:bool,
+    pub inverse:bool,
+    /// Double-width glyph covering this cell and the next.
+    pub wide:bool,
+    pub mark:Option<&'static str>
+}
+
+impl CellStyle{
+    pub fn from_cell(cell:&vt100::Cell)->CellStyle{
+        CellStyle{
+            fg:cell.fgcolor(),
+            bg:cell.bgcolor(),
+            bold:cell.bold(),
+            italic:cell.italic(),
+            underline:cell.underline(),
+            inverse:cell.inverse(),
+            wide:cell.is_wide(),
+            mark:None
+        }
+    }
+
+    /// ` class=".." style=".."` for an opening tag, empty for default text.
+    pub fn attributes(&self)->String{
+        let (fg,bg) = if self.inverse{
+            (self.bg,self.fg)
+        }else{
+            (self.fg,self.bg)
+        };
+        let mut classes = vec![];
+        let mut styles = vec![];
+        for (color,layer,property) in [(fg,"fg","color"),(bg,"bg","background-color")].iter(){
+            match color{
+                Default=>{}
+                Idx(index) if *index<16=>classes.push(format!("term-{}-{}",layer,index)),
+                Idx(index)=>styles.push(format!("{}:{}",property,xterm_color(*index))),
+                Rgb(r,g,b)=>styles.push(format!("{}:rgb({},{},{})",property,r,g,b))
+            }
+        }
+        if self.bold{
+            classes.push("term-bold".to_string());
+        }
+        if self.italic{
+            classes.push("term-italic".to_string());
+        }
+        if self.underline{
+            classes.push("term-underline".to_string());
+        }
+        if self.inverse{
+            classes.push("term-inverse".to_string());
+        }
+        if self.wide{
+            classes.push("term-wide".to_string());
+        }
+        if let Some(mark)=self.mark{
+            classes.push(mark.to_string());
+        }
+
+        let mut attrs = String::new();
+        if !classes.is_empty(){
+            attrs+=&format!(r#" class="{}""#,classes.join(" "));
+        }
+        if !styles.is_empty(){
+            attrs+=&format!(r#" style="{}""#,styles.join(";"));
+        }
+        attrs
+    }
+}
+
+/// CSS color of xterm palette entries 16-255 (the 6x6x6 cube and gray ramp).
+fn xterm_color(index:u8)->String{
+    if index>=232{
+        let level = 8+10*(index-232);
+        return format!("rgb({0},{0},{0})",level);
+    }
+    let levels = [0,95,135,175,215,255];
+    let index = (index-16) as usize;
+    format!("rgb({},{},{})",levels[index/36],levels[(index/6)%6],levels[index%6])
+}
+
+/// Where a `Terminal` reports user interaction.
+pub struct TerminalCallbacks{
+    /// Input for the process.
+    pub on_data:yew::Callback<String>,
+    /// Lines to scroll the view by, negative being back into history.
+    pub on_scroll:yew::Callback<isize>,
+    /// The parent or the window changed size; call `fit` in response.
+    pub on_resize:yew::Callback<()>,
+    /// Mouse activity over the screen; pass it on to `mouse`.
+    pub on_mouse:yew::Callback<MouseInput>,
+    /// Ctrl+Shift+C; call `copy_selection`.
+    pub on_copy:yew::Callback<()>
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum MouseKind{
+    Down,
+    Move,
+    Up,
+    WheelUp,
+    WheelDown
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct MouseInput{
+    pub kind:MouseKind,
+    /// `MouseEvent.button`: 0 left, 1 middle, 2 right.
+    pub button:i16,
+    pub row:u16,
+    pub col:u16,
+    pub shift:bool,
+    pub alt:bool,
+    pub ctrl:bool
+}
+
+/// Escape sequence reporting `input` to a program that enabled mouse
+/// tracking, or `None` when the mode does not ask for this event. Releases
+/// are only reported for a press this terminal saw, since every terminal
+/// hears every mouseup on the page.
+fn mouse_report(input:&MouseInput,pressed:Option<i16>,modes:InputModes)->Option<String>{
+    use vt100::MouseProtocolMode as Mode;
+    let (button,release,motion) = match input.kind{
+        MouseKind::Down=>(input.button.min(2) as u32,false,false),
+        MouseKind::Up if modes.mouse_mode!=Mode::Press=>(pressed?.min(2) as u32,true,false),
+        MouseKind::Move=>match (modes.mouse_mode,pressed){
+            (Mode::ButtonMotion,Some(button)) | (Mode::AnyMotion,Some(button))=>(button.min(2) as u32,false,true),
+            // 3 is "no button".
+            (Mode::AnyMotion,None)=>(3,false,true),
+            _=>return None
+        },
+        MouseKind::WheelUp=>(64,false,false),
+        MouseKind::WheelDown=>(65,false,false),
+        _=>return None
+    };
+    let mut code = button;
+    if input.shift{
+        code+=4;
+    }
+    if input.alt{
+        code+=8;
+    }
+    if input.ctrl{
+        code+=16;
+    }
+    if motion{
+        code+=32;
+    }
+    let (col,row) = (input.col as u32+1,input.row as u32+1);
+    match modes.mouse_encoding{
+        vt100::MouseProtocolEncoding::Sgr=>{
+            Some(format!("\x1b[<{};{};{}{}",code,col,row,if release { 'm' } else { 'M' }))
+        }
+        encoding=>{
+            // The legacy encodings cannot tell which button was released.
+            let code = if release { (code & !3) | 3 } else { code };
+            // Coordinates travel as text; plain X10 must stay within ASCII.
+            let limit = if encoding==vt100::MouseProtocolEncoding::Utf8 { 2015 } else { 95 };
+            if col>limit || row>limit{
+                return None;
+            }
+            let mut out = "\x1b[M".to_string();
+            for value in [code,col,row].iter(){
+                out.push(std::char::from_u32(32+value)?);
+            }
+            Some(out)
+        }
+    }
+}
+
+/// Selected cells from `anchor` (where the drag started) to `head`, inclusive.
+#[derive(Clone,Copy,Debug,PartialEq)]
+struct Selection{
+    anchor:(u16,u16),
+    head:(u16,u16)
+}
+
+impl Selection{
+    fn ordered(&self)->((u16,u16),(u16,u16)){
+        if self.anchor<=self.head{
+            (self.anchor,self.head)
+        }else{
+            (self.head,self.anchor)
+        }
+    }
+}
+
+/// Width and height of one cell, measured on the probe span.
+fn cell_size(probe:&web_sys::Element)->Option<(f64,f64)>{
+    let rect = probe.get_bounding_client_rect();
+    let size = (rect.width()/PROBE_TEXT.len() as f64,rect.height());
+    // Nothing is laid out while the terminal is hidden.
+    if size.0<=0.0 || size.1<=0.0{
+        None
+    }else{
+        Some(size)
     }
 }
 
+fn mouse_input(kind:MouseKind,event:&web_sys::MouseEvent,termdiv:&web_sys::Element,probe:&web_sys::Element)->Option<MouseInput>{
+    let (cell_width,cell_height) = cell_size(probe)?;
+    let rect = termdiv.get_bounding_client_rect();
+    Some(MouseInput{
+        kind,
+        button:event.button(),
+        row:((event.client_y() as f64-rect.top())/cell_height).floor().max(0.0) as u16,
+        col:((event.client_x() as f64-rect.left())/cell_width).floor().max(0.0) as u16,
+        shift:event.shift_key(),
+        alt:event.alt_key(),
+        ctrl:event.ctrl_key()
+    })
+}
+
 pub struct Terminal{
     onData:yew::Callback<String>,
     parser:VtParser,
     parent:HtmlElement,
-    termdiv:web_sys::Element,
+    screen:ScreenRenderer,
     inputListener:EventListener,
     keydownListener:EventListener,
     clickListender:EventListener,
+    wheelListener:EventListener,
+    windowResizeListener:EventListener,
+    pasteListener:EventListener,
+    mouseListeners:Vec<EventListener>,
+    resizeObserver:ResizeObserver,
+    resizeCallback:Closure<dyn FnMut()>,
+    /// Hidden run of characters used to measure the cell size.
+    probe:web_sys::Element,
+    modes:Rc<Cell<InputModes>>,
+    /// Query of the active search, empty when there is none.
+    query:String,
+    /// Set when output arrived since `matches` were computed.
+    search_stale:bool,
+    matches:Vec<SearchMatch>,
+    current_match:Option<usize>,
+    selection:Option<Selection>,
+    selecting:bool,
+    /// Button held while a program tracks the mouse.
+    pressed:Option<i16>,
+    /// `bell_count` when alerts were last taken.
+    bells_seen:usize,
     textarea:HtmlElement
 }
+
+/// What a program asked the user's attention for.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Alerts{
+    pub bell:bool,
+    pub notifications:Vec<OscNotification>
+}
 impl Terminal {
 
-    pub fn new(parent:HtmlElement,cb:yew::Callback<String>,cols:u16)->Result<Terminal,JsValue>{
+    pub fn new(parent:HtmlElement,callbacks:TerminalCallbacks,scrollback:usize)->Result<Terminal,JsValue>{
+        let TerminalCallbacks{on_data:cb,on_scroll,on_resize,on_mouse,on_copy} = callbacks;
         let window = web_sys::window().expect("no global `window` exists");
         let document = window.document().expect("should have a document on window");
         let el = document.create_element("textarea")?;
         let tdiv = document.create_element("div")?;
-        tdiv.set_attribute("style","font-family:Courier New;white-space:pre;")?;
+        tdiv.set_attribute("class","dcode-term")?;
+        tdiv.set_attribute("style","font-family:Courier New;white-space:pre;line-height:1.2;user-select:none;")?;
+        let probe = document.create_element("span")?;
+        probe.set_attribute("style","position:absolute;visibility:hidden;")?;
+        probe.set_text_content(Some(PROBE_TEXT));
+        tdiv.append_child(&probe)?;
         el.set_attribute("autocomplete","off")?;
         el.set_attribute("autocapitalize","none")?;
         el.set_attribute("style",r#"
@@ -158,37 +965,82 @@ impl Terminal {
         let click_listenner = EventListener::new(&tdiv,"click",move |_ev|{
             elc.clone().dyn_into::<HtmlElement>().expect("NotHtmlElement").focus();
         });
-        let key_down_ev = EventListener::new(&el, "keydown", move |event|{
-            log::info!("Received keydown");
+        let modes = Rc::new(Cell::new(InputModes::default()));
+        let wheel_scroll = on_scroll.clone();
+        let (wheel_mouse,wheel_modes,wheel_termdiv,wheel_probe) = (on_mouse.clone(),modes.clone(),tdiv.clone(),probe.clone());
+        let wheel_listener = EventListener::new_with_options(&tdiv,"wheel",EventListenerOptions::enable_prevent_default(),move |event|{
+            let wheel_event = event.clone().dyn_into::<web_sys::WheelEvent>().expect("Not wheel event");
+            event.prevent_default();
+            let lines = if wheel_event.delta_y()<0.0 { -3 } else if wheel_event.delta_y()>0.0 { 3 } else { 0 };
+            if lines==0{
+                return;
+            }
+            // Shift keeps the wheel on scrollback while a program grabs the mouse.
+            if wheel_modes.get().mouse_mode!=vt100::MouseProtocolMode::None && !wheel_event.shift_key(){
+                let kind = if lines<0 { MouseKind::WheelUp } else { MouseKind::WheelDown };
+                if let Some(input)=mouse_input(kind,&wheel_event,&wheel_termdiv,&wheel_probe){
+                    wheel_mouse.emit(input);
+                }
+            }else{
+                wheel_scroll.emit(lines);
+            }
+        });
+        let window_resize = on_resize.clone();
+        let window_resize_listener = EventListener::new(&window,"resize",move |_event|{
+            window_resize.emit(());
+        });
+        let resize_callback = Closure::wrap(Box::new(move ||{
+            on_resize.emit(());
+        }) as Box<dyn FnMut()>);
+        let resize_observer = ResizeObserver::new(resize_callback.as_ref().unchecked_ref());
+        resize_observer.observe(&parent);
+        let mut mouse_listeners = vec![];
+        let any_motion = modes.clone();
+        for (target,name,kind) in [
+            (tdiv.clone().dyn_into::<web_sys::EventTarget>()?,"mousedown",MouseKind::Down),
+            (tdiv.clone().dyn_into()?,"mousemove",MouseKind::Move),
+            // Released anywhere, so a drag can end outside the terminal.
+            (window.clone().dyn_into()?,"mouseup",MouseKind::Up)
+        ]{
+            let (termdiv,probe,on_mouse,any_motion) = (tdiv.clone(),probe.clone(),on_mouse.clone(),any_motion.clone());
+            mouse_listeners.push(EventListener::new(&target,name,move |event|{
+                let mouse_event = event.clone().dyn_into::<web_sys::MouseEvent>().expect("Not mouse event");
+                if kind==MouseKind::Move && mouse_event.buttons()==0
+                    && any_motion.get().mouse_mode!=vt100::MouseProtocolMode::AnyMotion{
+                    return;
+                }
+                if let Some(input)=mouse_input(kind,&mouse_event,&termdiv,&probe){
+                    on_mouse.emit(input);
+                }
+            }));
+        }
+        let key_modes = modes.clone();
+        let paste_modes = modes.clone();
+        let paste_data = cb.clone();
+        let paste_listener = EventListener::new_with_options(&el,"paste",EventListenerOptions::enable_prevent_default(),move |event|{
+            let paste_event = event.clone().dyn_into::<web_sys::ClipboardEvent>().expect("Not clipboard event");
+            let text = paste_event.clipboard_data().and_then(|data|data.get_data("text/plain").ok());
+            event.prevent_default();
+            if let Some(text)=text.filter(|text|!text.is_empty()){
+                paste_data.emit(paste_input(&text,paste_modes.get().bracketed_paste));
+            }
+        });
+        let key_down_ev = EventListener::new_with_options(&el, "keydown", EventListenerOptions::enable_prevent_default(), move |event|{
             let key_event = event.clone().dyn_into::<web_sys::KeyboardEvent>().expect("Not key event");
-            let keycode = key_event.key_code();
             let inpel = el3.clone().dyn_into::<HtmlTextAreaElement>().expect("Not inputElement");
-            let this = JsValue::NULL;
 
+            // A non-empty textarea means an IME composition is in progress.
             if inpel.value().is_empty(){
 
-                if key_event.ctrl_key(){
-
-                    let ucode = keycode-64;
-                    match ucode.try_into(){
-                        Ok(code) => {
-
-                            let stri = String::from_utf8(vec![code]);
-                            if let Ok(val) = stri{
-
-                                cb3.emit(val);
-                            }
-                        }
-                        Err(err)=>{
-                            log::error!("Cannot convert to string {}",err);
-                        }
-                    }
-                }else{
-                    let key = key_event.key();
-                    if let Some(code)=KEYCODEMAP.get(&key){
-                        cb3.emit(code.to_string())
-
-                    }
+                if key_event.ctrl_key() && key_event.shift_key() && key_event.key().eq_ignore_ascii_case("c"){
+                    event.prevent_default();
+                    on_copy.emit(());
+                }else if key_event.shift_key() && (key_event.key()=="PageUp" || key_event.key()=="PageDown"){
+                    event.prevent_default();
+                    on_scroll.emit(if key_event.key()=="PageUp" { -(SCROLL_PAGE as isize) } else { SCROLL_PAGE as isize });
+                }else if let Some(data)=encode_key(&key_event,key_modes.get()){
+                    event.prevent_default();
+                    cb3.emit(data);
                 }
             }
 
@@ -196,12 +1048,28 @@ impl Terminal {
         });
         let term = Terminal{
             onData:cb,
-            parser:VtParser::new(24,cols,0.0),
+            parser:VtParser::new(24,80,scrollback,0.0),
             parent,
             inputListener:ev,
             keydownListener:key_down_ev,
-            termdiv:tdiv,
+            screen:ScreenRenderer::new(tdiv),
             clickListender:click_listenner,
+            wheelListener:wheel_listener,
+            windowResizeListener:window_resize_listener,
+            pasteListener:paste_listener,
+            mouseListeners:mouse_listeners,
+            resizeObserver:resize_observer,
+            resizeCallback:resize_callback,
+            probe,
+            modes,
+            query:String::new(),
+            search_stale:false,
+            matches:vec![],
+            current_match:None,
+            selection:None,
+            selecting:false,
+            pressed:None,
+            bells_seen:0,
             textarea:el.clone().dyn_into().unwrap()
         };
         // let f = Closure::wrap(Box::new(move ||{term.parser.parse("");}));
@@ -212,29 +1080,514 @@ impl Terminal {
     }
 
     pub fn write(&mut self,inp:&str){
+        let alternate = self.parser.alternate_screen();
         self.parser.parse(inp);
-        let htt = self.parser.html_content();
-        self.termdiv.set_inner_html(&htt);
+        self.modes.set(self.parser.input_modes());
+        if self.parser.alternate_screen()!=alternate{
+            // History, matches and selection all belong to the other screen.
+            self.parser.set_scroll_offset(0);
+            self.query.clear();
+            self.search_stale=false;
+            self.matches.clear();
+            self.current_match=None;
+            self.selection=None;
+            self.selecting=false;
+            self.screen.invalidate();
+        }else if !self.query.is_empty(){
+            // Searching walks the whole history, too slow for every chunk;
+            // the owner calls `refresh_search` once output settles.
+            self.search_stale=true;
+        }
+        self.render();
+        self.place_textarea();
+    }
+
+    /// Keeps the hidden textarea at the cursor cell so IME popups show there.
+    fn place_textarea(&self){
+        let (cell_width,cell_height) = match cell_size(&self.probe){
+            Some(size)=>size,
+            None=>return
+        };
+        let rect = self.screen.element().get_bounding_client_rect();
+        let (row,col) = self.parser.cursor_position();
+        let _ = self.textarea.set_attribute("style",&format!(r#"
+            position:fixed;
+            left:{}px;
+            top:{}px;
+            opacity:0;
+            height:0;
+            width:0;
+            z-index:-200;
+        "#,rect.left()+col as f64*cell_width,rect.top()+(row as f64+1.0)*cell_height));
+    }
+
+    /// Bells and notifications since the last call.
+    pub fn take_alerts(&mut self)->Alerts{
+        let bells = self.parser.bell_count();
+        let bell = bells!=self.bells_seen;
+        self.bells_seen=bells;
+        Alerts{
+            bell,
+            notifications:self.parser.take_notifications()
+        }
+    }
+
+    pub fn focus(&self){
+        let _ = self.textarea.focus();
+    }
+
+    /// Whether output arrived since the last search.
+    pub fn search_stale(&self)->bool{
+        self.search_stale
+    }
+
+    /// Re-runs the active search after new output. Trimmed history shifts
+    /// line indices, so the current match is dropped.
+    pub fn refresh_search(&mut self){
+        if !self.search_stale{
+            return;
+        }
+        self.search_stale=false;
+        self.matches=self.parser.search(&self.query);
+        self.current_match=None;
+        self.render();
+    }
+
+    pub fn match_count(&self)->usize{
+        self.matches.len()
+    }
+
+    pub fn current_match(&self)->Option<usize>{
+        self.current_match
+    }
 
-        let cursor = self.parent.clone().dyn_into::<web_sys::Element>().unwrap().query_selector("#termcursor");
-        if let Ok(cursor)=cursor{
-            if let Some(cursor)=cursor{
-                let pos = cursor.get_bounding_client_rect();
-                self.textarea.set_attribute("style",&format!(r#"
-                    position:fixed;
-                    left:{}px;
-                    top:{}px;
-                    opacity:0;
-                    height:0;
-                    width:0;
-                    z-index:-200;
-                "#,pos.right(),pos.bottom()));
+    pub fn size(&self)->(u16,u16){
+        self.parser.size()
+    }
+
+    /// Resizes the screen to the rows and columns that fit in the parent.
+    /// Returns the new size when it changed.
+    pub fn fit(&mut self)->Option<(u16,u16)>{
+        let (cell_width,cell_height) = cell_size(&self.probe)?;
+        let cols = ((self.parent.client_width() as f64/cell_width).floor() as u16).max(MIN_COLS);
+        let rows = ((self.parent.client_height() as f64/cell_height).floor() as u16).max(MIN_ROWS);
+        if (rows,cols)==self.parser.size(){
+            return None;
+        }
+        self.parser.set_size(rows,cols);
+        self.selection=None;
+        self.screen.invalidate();
+        self.render();
+        Some((rows,cols))
+    }
+
+    /// Reports mouse activity to the program when it enabled mouse tracking,
+    /// returning the input to send; otherwise (or with Shift held) left-button
+    /// drags select cells.
+    pub fn mouse(&mut self,mut input:MouseInput)->Option<String>{
+        let (rows,cols) = self.parser.size();
+        input.row=input.row.min(rows-1);
+        input.col=input.col.min(cols-1);
+        let modes = self.modes.get();
+        if modes.mouse_mode!=vt100::MouseProtocolMode::None && (!input.shift || self.pressed.is_some()) && !self.selecting{
+            let report = mouse_report(&input,self.pressed,modes);
+            match input.kind{
+                MouseKind::Down=>self.pressed=Some(input.button),
+                MouseKind::Up=>self.pressed=None,
+                _=>{}
+            }
+            return report;
+        }
+        let cell = (input.row,input.col);
+        match input.kind{
+            MouseKind::Down if input.button==0=>{
+                self.selection=Some(Selection{anchor:cell,head:cell});
+                self.selecting=true;
+            }
+            MouseKind::Move if self.selecting=>{
+                if let Some(selection)=&mut self.selection{
+                    selection.head=cell;
+                }
+            }
+            MouseKind::Up if self.selecting=>{
+                self.selecting=false;
+                if self.selection.map(|selection|selection.anchor==selection.head).unwrap_or(false){
+                    self.selection=None;
+                }
             }
+            _=>return None
         }
+        self.render();
+        None
+    }
+
+    pub fn selected_text(&self)->Option<String>{
+        let (start,end) = self.selection?.ordered();
+        Some(self.parser.contents_between(start,end))
+    }
+
+    pub fn copy_selection(&self){
+        let text = match self.selected_text(){
+            Some(text)=>text,
+            None=>return
+        };
+        match clipboard(){
+            Some(clipboard)=>{
+                let _ = clipboard.write_text(&text);
+            }
+            None=>log::error!("Clipboard API unavailable")
+        }
+    }
+
+    /// Moves the view through scrollback; positive `lines` go towards the live screen.
+    pub fn scroll(&mut self,lines:isize){
+        let offset = self.parser.scroll_offset() as isize - lines;
+        self.parser.set_scroll_offset(offset.max(0) as usize);
+        self.selection=None;
+        self.render();
+    }
+
+    pub fn scroll_to_bottom(&mut self){
+        if self.parser.scroll_offset()!=0{
+            self.parser.set_scroll_offset(0);
+            self.render();
+        }
+    }
+
+    /// Highlights every match of `query` and jumps to the most recent one.
+    /// Returns the number of matches.
+    pub fn find(&mut self,query:&str)->usize{
+        self.query=query.to_string();
+        self.search_stale=false;
+        self.matches=self.parser.search(query);
+        self.current_match=self.matches.len().checked_sub(1);
+        self.reveal_match();
+        self.matches.len()
+    }
+
+    /// Steps to the next (or previous) match, wrapping around.
+    pub fn find_next(&mut self,forward:bool)->Option<usize>{
+        let count = self.matches.len();
+        if count==0{
+            return None;
+        }
+        self.current_match=Some(match self.current_match{
+            Some(idx) if forward=>(idx+1)%count,
+            Some(idx)=>(idx+count-1)%count,
+            None=>0
+        });
+        self.reveal_match();
+        self.current_match
+    }
+
+    pub fn clear_search(&mut self){
+        self.query.clear();
+        self.search_stale=false;
+        self.matches.clear();
+        self.current_match=None;
+        self.render();
+    }
+
+    fn reveal_match(&mut self){
+        if let Some(found)=self.current_match.and_then(|idx|self.matches.get(idx)).cloned(){
+            let history = self.parser.history_len();
+            let offset = history.saturating_sub(found.line);
+            self.parser.set_scroll_offset(offset);
+        }
+        self.render();
+    }
+
+    fn row_marks(&mut self,rows:usize)->Vec<Vec<Mark>>{
+        let mut marks = vec![vec![];rows];
+        if let Some((start,end))=self.selection.map(|selection|selection.ordered()){
+            let cols = self.parser.size().1;
+            for row in start.0..=end.0.min(rows as u16-1){
+                marks[row as usize].push(Mark{
+                    start:if row==start.0 { start.1 } else { 0 },
+                    end:if row==end.0 { end.1+1 } else { cols },
+                    class:"term-selected"
+                });
+            }
+        }
+        if self.matches.is_empty(){
+            return marks;
+        }
+        let top = self.parser.history_len()-self.parser.scroll_offset();
+        for (idx,found) in self.matches.iter().enumerate(){
+            if found.line>=top && found.line<top+rows{
+                marks[found.line-top].push(Mark{
+                    start:found.start,
+                    end:found.end,
+                    class:if Some(idx)==self.current_match { "term-match-current" } else { "term-match" }
+                });
+            }
+        }
+        marks
+    }
+
+    fn render(&mut self){
+        let rows = self.parser.size().0 as usize;
+        let marks = self.row_marks(rows);
+        self.screen.render(&self.parser,&marks);
     }
 
     pub fn get_title(&self)->String{
-        self.parser.parser.screen().title().to_string()
+        self.parser.title().to_string()
+    }
+
+}
+
+/// Keeps a div of row elements in sync with a `VtParser`, touching only the
+/// rows whose HTML changed since the last render.
+pub struct ScreenRenderer{
+    termdiv:web_sys::Element,
+    rows:Vec<web_sys::Element>,
+    row_hashes:Vec<Option<u64>>
+}
+
+impl ScreenRenderer{
+    pub fn new(termdiv:web_sys::Element)->ScreenRenderer{
+        ScreenRenderer{
+            termdiv,
+            rows:vec![],
+            row_hashes:vec![]
+        }
+    }
+
+    pub fn element(&self)->&web_sys::Element{
+        &self.termdiv
+    }
+
+    /// Forces every row to be redrawn on the next render.
+    pub fn invalidate(&mut self){
+        for hash in self.row_hashes.iter_mut(){
+            *hash=None;
+        }
+    }
+
+    /// `marks` holds the extra classes for each screen row, if any.
+    pub fn render(&mut self,parser:&VtParser,marks:&[Vec<Mark>]){
+        let rows = parser.size().0 as usize;
+        let document = web_sys::window().and_then(|window|window.document()).expect("should have a document on window");
+        while self.rows.len()<rows{
+            match document.create_element("div"){
+                Ok(row)=>{
+                    let _ = row.set_attribute("class","term-row");
+                    let _ = self.termdiv.append_child(&row);
+                    self.rows.push(row);
+                    self.row_hashes.push(None);
+                }
+                Err(err)=>{
+                    log::error!("Cannot create row {:?}",err);
+                    return;
+                }
+            }
+        }
+        while self.rows.len()>rows{
+            if let Some(row)=self.rows.pop(){
+                row.remove();
+            }
+            self.row_hashes.pop();
+        }
+
+        for row in 0..rows{
+            let html = parser.row_html(row as u16,marks.get(row).map(Vec::as_slice).unwrap_or(&[]));
+            let mut hasher = DefaultHasher::new();
+            html.hash(&mut hasher);
+            let hash = Some(hasher.finish());
+            if self.row_hashes[row]!=hash{
+                self.rows[row].set_inner_html(&html);
+                self.row_hashes[row]=hash;
+            }
+        }
+    }
+}
+
+impl Drop for Terminal{
+    fn drop(&mut self){
+        self.resizeObserver.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    /// Feeds `input` to a 3x10 screen with the cursor hidden, so rows only
+    /// contain cell output.
+    fn screen(input:&str)->VtParser{
+        let mut parser = VtParser::new(3,10,0,0.0);
+        parser.parse("\x1b[?25l");
+        parser.parse(input);
+        parser
+    }
+
+    #[test]
+    fn plain_text_pads_the_row(){
+        assert_eq!(screen("hello").row_html(0,&[]),"hello     ");
+    }
+
+    #[test]
+    fn markup_is_escaped(){
+        assert_eq!(screen("<b>&\"</b>").row_html(0,&[]),"&lt;b&gt;&amp;&quot;&lt;/b&gt; ");
+    }
+
+    #[test]
+    fn same_style_cells_share_a_span(){
+        assert_eq!(
+            screen("\x1b[31mred\x1b[0m ok").row_html(0,&[]),
+            r#"<span class="term-fg-1">red</span> ok    "#
+        );
+    }
+
+    #[test]
+    fn extended_colors_are_inlined(){
+        assert_eq!(
+            screen("\x1b[38;2;1;2;3mx\x1b[48;5;196my\x1b[0m").row_html(0,&[]),
+            r#"<span style="color:rgb(1,2,3)">x</span><span style="color:rgb(1,2,3);background-color:rgb(255,0,0)">y</span>        "#
+        );
+    }
+
+    #[test]
+    fn wide_glyphs_cover_two_columns(){
+        assert_eq!(
+            screen("a日本b").row_html(0,&[]),
+            r#"a<span class="term-wide">日</span><span class="term-wide">本</span>b    "#
+        );
+    }
+
+    #[test]
+    fn combining_marks_stay_in_their_cell(){
+        assert_eq!(screen("e\u{301}x").row_html(0,&[]),"e\u{301}x        ");
+    }
+
+    #[test]
+    fn marks_add_classes(){
+        let mark = Mark{start:1,end:3,class:"term-match"};
+        assert_eq!(
+            screen("abcd").row_html(0,&[mark]),
+            r#"a<span class="term-match">bc</span>d      "#
+        );
     }
 
+    #[test]
+    fn cursor_gets_its_own_span(){
+        let mut parser = VtParser::new(3,10,0,0.0);
+        parser.parse("ab");
+        assert_eq!(
+            parser.row_html(0,&[]),
+            r#"ab<span id="termcursor" class="term-cursor"><span> </span></span>       "#
+        );
+    }
+
+    #[test]
+    fn cursor_shape_is_tracked_across_chunks(){
+        let mut parser = VtParser::new(3,10,0,0.0);
+        parser.parse("\x1b[5");
+        parser.parse(" q");
+        assert_eq!(parser.cursor_shape(),CursorShape::Bar);
+    }
+
+    #[test]
+    fn alternate_screen_is_tracked_across_chunks(){
+        let mut parser = VtParser::new(3,10,0,0.0);
+        parser.parse("\x1b[?10");
+        parser.parse("49h");
+        assert!(parser.alternate_screen());
+        parser.parse("\x1b[?25;1049l");
+        assert!(!parser.alternate_screen());
+        parser.parse("\x1b[?47h\x1b[4 q\x1b");
+        parser.parse("c");
+        assert!(!parser.alternate_screen());
+        assert_eq!(parser.cursor_shape(),CursorShape::Block);
+    }
+
+    #[test]
+    fn osc_notifications_are_collected(){
+        let mut parser = VtParser::new(3,10,0,0.0);
+        parser.parse("\x1b]9;build done\x07\x1b]9;4;1;50\x07\x1b]777;notify;make;fin");
+        parser.parse("ished\x1b\\");
+        assert_eq!(parser.take_notifications(),vec![
+            OscNotification{title:None,body:"build done".to_string()},
+            OscNotification{title:Some("make".to_string()),body:"finished".to_string()}
+        ]);
+        assert!(parser.take_notifications().is_empty());
+    }
+
+    #[test]
+    fn conemu_subcommands_are_not_notifications(){
+        assert_eq!(OscNotification::parse("9;9;C:\\Users\\me"),None);
+        assert_eq!(OscNotification::parse("9;4;1;50"),None);
+        assert_eq!(OscNotification::parse("9;12;"),None);
+        assert_eq!(
+            OscNotification::parse("9;3 files copied; done"),
+            Some(OscNotification{title:None,body:"3 files copied; done".to_string()})
+        );
+    }
+
+    #[test]
+    fn search_spans_history(){
+        let mut parser = VtParser::new(2,10,10,0.0);
+        parser.parse("needle\r\none\r\ntwo\r\nNeedle");
+        let matches = parser.search("needle");
+        assert_eq!(matches.len(),2);
+        assert_eq!((matches[0].line,matches[0].start,matches[0].end),(0,0,6));
+        assert_eq!(matches[1].line,3);
+    }
+
+    #[test]
+    fn history_longer_than_the_screen(){
+        let mut parser = VtParser::new(2,10,10,0.0);
+        parser.parse("a\r\nb\r\nc\r\nd\r\ne");
+        assert_eq!(parser.history_len(),3);
+        assert_eq!(parser.lines(),vec!["a","b","c","d","e"]);
+        let matches = parser.search("a");
+        assert_eq!(matches.len(),1);
+        assert_eq!((matches[0].line,matches[0].start,matches[0].end),(0,0,1));
+        // Scrolled back past a full screen, the view starts in history.
+        parser.set_scroll_offset(3);
+        assert_eq!(parser.scroll_offset(),3);
+        assert_eq!(parser.contents_between((0,0),(1,9)),"a\nb");
+    }
+
+    #[test]
+    fn selection_text_follows_cells(){
+        let parser = screen("ab日本cd\r\n  x");
+        assert_eq!(parser.contents_between((0,1),(0,4)),"b日本");
+        assert_eq!(parser.contents_between((0,6),(1,2)),"cd\n  x");
+        assert_eq!(parser.contents_between((1,3),(2,9)),"\n");
+    }
+
+    #[test]
+    fn paste_cannot_end_bracketed_paste(){
+        assert_eq!(paste_input("a\r\nb\nc",false),"a\rb\rc");
+        assert_eq!(
+            paste_input("ls\x1b[201~rm -rf ~\n",true),
+            "\x1b[200~ls[201~rm -rf ~\r\x1b[201~"
+        );
+    }
+
+    #[test]
+    fn mouse_release_needs_a_press(){
+        let modes = InputModes{
+            mouse_mode:vt100::MouseProtocolMode::ButtonMotion,
+            mouse_encoding:vt100::MouseProtocolEncoding::Sgr,
+            ..InputModes::default()
+        };
+        let up = MouseInput{kind:MouseKind::Up,button:0,row:1,col:2,shift:false,alt:false,ctrl:false};
+        assert_eq!(mouse_report(&up,None,modes),None);
+        assert_eq!(mouse_report(&up,Some(0),modes),Some("\x1b[<0;3;2m".to_string()));
+    }
+
+    #[test]
+    fn search_reports_cell_columns(){
+        let mut parser = VtParser::new(2,20,0,0.0);
+        parser.parse("日本 needle e\u{301}x");
+        let matches = parser.search("NEEDLE");
+        assert_eq!((matches[0].start,matches[0].end),(5,11));
+        let matches = parser.search("\u{301}x");
+        assert_eq!((matches[0].start,matches[0].end),(12,14));
+        let matches = parser.search("本");
+        assert_eq!((matches[0].start,matches[0].end),(2,4));
+    }
 }